@@ -0,0 +1,20 @@
+use rusty_vim::{Editor, HeadlessBackend, Result};
+
+// types into a scratch buffer and prints the rendered screen
+fn main() -> Result<()> {
+    let backend = HeadlessBackend::new(60, 8);
+    let mut editor = Editor::with_backend(Vec::new(), Box::new(backend.clone()))?;
+    editor.feed_keys(b"ihello from rusty-vim\x1b")?;
+    if let Err(e) = editor.execute("set list") {
+        eprintln!("{}", e);
+    }
+    if let Some(buffer) = editor.buffer() {
+        println!(
+            "buffer has {} line(s): {:?}",
+            buffer.line_count(),
+            buffer.line(0)
+        );
+    }
+    println!("{}", backend.lines().join("\n"));
+    Ok(())
+}
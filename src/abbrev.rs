@@ -0,0 +1,39 @@
+#[derive(Default)]
+pub struct Abbreviations {
+    abbrevs: Vec<(String, String)>,
+}
+
+impl Abbreviations {
+    pub fn add(&mut self, lhs: &str, rhs: &str) {
+        self.abbrevs.retain(|(word, _)| word != lhs);
+        self.abbrevs.push((lhs.to_string(), rhs.to_string()));
+    }
+
+    pub fn remove(&mut self, lhs: &str) -> Result<(), String> {
+        let len = self.abbrevs.len();
+        self.abbrevs.retain(|(word, _)| word != lhs);
+        if self.abbrevs.len() == len {
+            return Err(String::from("No such abbreviation"));
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.abbrevs.clear();
+    }
+
+    pub fn get(&self, lhs: &str) -> Option<&str> {
+        self.abbrevs
+            .iter()
+            .find(|(word, _)| word == lhs)
+            .map(|(_, expansion)| expansion.as_str())
+    }
+
+    pub fn list(&self, prefix: &str) -> Vec<String> {
+        self.abbrevs
+            .iter()
+            .filter(|(word, _)| word.starts_with(prefix))
+            .map(|(word, expansion)| format!("i  {:<12} {}", word, expansion))
+            .collect()
+    }
+}
@@ -0,0 +1,61 @@
+use crate::error::AppError;
+use std::{ffi::OsStr, path::Path};
+
+pub enum ConfigFile {
+    Default,
+    Path(String),
+    None,
+}
+
+pub struct Args {
+    pub files: Vec<String>,
+    pub config: ConfigFile,
+    pub headless: bool,
+    pub script: Option<String>,
+    pub recover: bool,
+    pub readonly: bool,
+}
+
+impl Args {
+    pub fn parse(args: Vec<String>) -> Result<Self, AppError> {
+        let mut files = Vec::new();
+        let mut config = ConfigFile::Default;
+        let mut headless = false;
+        let mut script = None;
+        let mut recover = false;
+        let mut args = args.into_iter();
+        // started as `view` it is `-R`
+        let mut readonly = args
+            .next()
+            .is_some_and(|program| Path::new(&program).file_name() == Some(OsStr::new("view")));
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-u" => {
+                    config = match args.next() {
+                        Some(path) if path == "NONE" => ConfigFile::None,
+                        Some(path) => ConfigFile::Path(path),
+                        None => return Err(AppError::ArgError(String::from("-u"))),
+                    }
+                }
+                "-s" => {
+                    script = Some(args.next().ok_or(AppError::ArgError(String::from("-s")))?);
+                }
+                "--headless" => headless = true,
+                "-r" => recover = true,
+                "-R" => readonly = true,
+                "--" => {
+                    files.extend(args.by_ref());
+                }
+                _ => files.push(arg),
+            }
+        }
+        Ok(Self {
+            files,
+            config,
+            headless,
+            script,
+            recover,
+            readonly,
+        })
+    }
+}
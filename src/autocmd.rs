@@ -0,0 +1,107 @@
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Event {
+    BufNewFile,
+    BufRead,
+    BufEnter,
+    BufWritePost,
+}
+
+impl Event {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bufnewfile" => Some(Event::BufNewFile),
+            "bufread" | "bufreadpost" => Some(Event::BufRead),
+            "bufenter" => Some(Event::BufEnter),
+            "bufwritepost" => Some(Event::BufWritePost),
+            _ => None,
+        }
+    }
+}
+
+struct AutoCmd {
+    event: Event,
+    pattern: String,
+    command: String,
+}
+
+#[derive(Default)]
+pub struct AutoCmds {
+    cmds: Vec<AutoCmd>,
+}
+
+impl AutoCmds {
+    pub fn add(&mut self, args: &str) -> Result<(), String> {
+        let mut parts = args.splitn(3, char::is_whitespace);
+        let (Some(events), Some(pattern), Some(command)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("Invalid autocmd: {}", args));
+        };
+        for name in events.split(',') {
+            let event = Event::parse(name).ok_or(format!("No such event: {}", name))?;
+            self.cmds.push(AutoCmd {
+                event,
+                pattern: pattern.to_string(),
+                command: command.trim().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self, events: &str) -> Result<(), String> {
+        if events.is_empty() {
+            self.cmds.clear();
+            return Ok(());
+        }
+        for name in events.split(',') {
+            let event = Event::parse(name).ok_or(format!("No such event: {}", name))?;
+            self.cmds.retain(|cmd| cmd.event != event);
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.cmds
+            .iter()
+            .map(|cmd| format!("{:?}  {}  {}", cmd.event, cmd.pattern, cmd.command))
+            .collect()
+    }
+
+    pub fn matching(&self, event: Event, filename: &str) -> Vec<String> {
+        self.cmds
+            .iter()
+            .filter(|cmd| cmd.event == event)
+            .filter(|cmd| {
+                cmd.pattern
+                    .split(',')
+                    .any(|pattern| pattern_matches(pattern, filename))
+            })
+            .map(|cmd| cmd.command.clone())
+            .collect()
+    }
+}
+
+fn pattern_matches(pattern: &str, filename: &str) -> bool {
+    if pattern.contains('/') {
+        return glob_match(pattern.as_bytes(), filename.as_bytes());
+    }
+    let name = Path::new(filename)
+        .file_name()
+        .map_or(filename, |name| name.to_str().unwrap_or(filename));
+    glob_match(pattern.as_bytes(), name.as_bytes())
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
@@ -0,0 +1,102 @@
+use super::{Backend, CursorShape};
+use crate::{
+    error::{AppError, Result},
+    screen::{Cell, Screen},
+    terminal::Position,
+    theme::Theme,
+};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+struct Headless {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    cursor: Position,
+    shape: CursorShape,
+    keys: VecDeque<u8>,
+}
+
+// records every frame in memory and reads keys from a script, clones share the same state
+#[derive(Clone)]
+pub struct HeadlessBackend {
+    inner: Rc<RefCell<Headless>>,
+}
+
+impl HeadlessBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(Headless {
+                width,
+                height,
+                cells: vec![Cell::default(); width * height],
+                cursor: Position::new(),
+                shape: CursorShape::Block,
+                keys: VecDeque::new(),
+            })),
+        }
+    }
+
+    pub fn push_keys(&self, keys: &[u8]) {
+        self.inner.borrow_mut().keys.extend(keys);
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<Cell> {
+        let inner = self.inner.borrow();
+        (x < inner.width && y < inner.height).then(|| inner.cells[y * inner.width + x])
+    }
+
+    pub fn cursor(&self) -> (Position, CursorShape) {
+        let inner = self.inner.borrow();
+        (inner.cursor, inner.shape)
+    }
+
+    // the text of each row with trailing blanks removed
+    pub fn lines(&self) -> Vec<String> {
+        let inner = self.inner.borrow();
+        inner
+            .cells
+            .chunks(inner.width.max(1))
+            .map(|row| {
+                let line: String = row.iter().map(|cell| cell.ch).collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn size(&mut self) -> Result<Position> {
+        let inner = self.inner.borrow();
+        Ok(Position {
+            x: inner.width,
+            y: inner.height,
+        })
+    }
+
+    fn read_key(&mut self) -> Result<u8> {
+        self.inner
+            .borrow_mut()
+            .keys
+            .pop_front()
+            .ok_or(AppError::EndOfInput)
+    }
+
+    fn draw(
+        &mut self,
+        screen: &Screen,
+        _theme: &Theme,
+        cursor: &Position,
+        shape: CursorShape,
+    ) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        inner.cursor = *cursor;
+        inner.shape = shape;
+        for y in 0..screen.height.min(inner.height) {
+            for x in 0..screen.width.min(inner.width) {
+                let width = inner.width;
+                inner.cells[y * width + x] = screen.get(x, y);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+pub mod headless;
+pub mod termios;
+
+use crate::{error::Result, screen::Screen, terminal::Position, theme::Theme};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+}
+
+pub trait Backend {
+    fn size(&mut self) -> Result<Position>;
+
+    // 0 when no key arrived in time, Err(EndOfInput) once there is nothing left to read
+    fn read_key(&mut self) -> Result<u8>;
+
+    // moves rows top..bottom of what is on screen by lines (positive moves content up)
+    fn scroll(&mut self, _top: usize, _bottom: usize, _lines: isize) {}
+
+    // forget what is on screen so the next draw repaints everything
+    fn invalidate(&mut self) {}
+
+    fn draw(
+        &mut self,
+        screen: &Screen,
+        theme: &Theme,
+        cursor: &Position,
+        shape: CursorShape,
+    ) -> Result<()>;
+}
@@ -0,0 +1,194 @@
+use super::{Backend, CursorShape};
+use crate::{
+    error::{AppError, Result},
+    screen::{Cell, Screen},
+    terminal::Position,
+    theme::Theme,
+};
+use std::{
+    io::{self, Read, Write, stdout},
+    os::fd::AsRawFd,
+};
+use termios::*;
+
+pub struct TermiosBackend {
+    termios: Termios,
+    // a model of what the terminal currently shows, None where unknown
+    drawn: Vec<Option<Cell>>,
+    width: usize,
+    pending: String,
+}
+
+impl TermiosBackend {
+    pub fn new() -> Result<Self> {
+        let fd = io::stdin().as_raw_fd();
+        let backend = Self {
+            termios: Termios::from_fd(fd)?,
+            drawn: Vec::new(),
+            width: 0,
+            pending: String::new(),
+        };
+        Self::enable_raw_mode(fd)?;
+        Ok(backend)
+    }
+
+    fn enable_raw_mode(fd: i32) -> Result<()> {
+        write!(io::stdout(), "\x1b[?1049h").expect("write");
+        stdout().flush().expect("flush");
+
+        let mut termios = Termios::from_fd(fd)?;
+        termios.c_iflag &= !(INPCK | ISTRIP | BRKINT | IXON | ICRNL);
+        termios.c_oflag &= !(OPOST);
+        termios.c_cflag |= CS8;
+        termios.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+        termios.c_cc[VMIN] = 0;
+        termios.c_cc[VTIME] = 1;
+
+        tcsetattr(fd, TCSAFLUSH, &termios)?;
+        Ok(())
+    }
+
+    fn get_cursor_pos() -> Result<Position> {
+        let mut response = String::new();
+        write!(io::stdout(), "\x1b[6n")?;
+        stdout().flush()?;
+        let mut buf = [0; 1];
+        loop {
+            io::stdin().read_exact(&mut buf)?;
+            let c = buf[0] as char;
+            if c == 'R' {
+                break;
+            }
+            response.push(c);
+        }
+        if !response.starts_with("\x1b[") {
+            return Err(AppError::TermError);
+        }
+        let parts: Vec<&str> = response[2..].split(';').collect();
+        let rows = parts[0].parse::<usize>()?;
+        let cols = parts[1].parse::<usize>()?;
+        Ok(Position { x: cols, y: rows })
+    }
+
+    fn handle_other_keys(&self, seq: &[u8]) -> u8 {
+        if seq[1] == b'[' {
+            match seq[2] as char {
+                'A' => b'k',
+                'B' => b'j',
+                'C' => b'l',
+                'D' => b'h',
+                _ => b'\x1b',
+            }
+        } else {
+            b'\x1b'
+        }
+    }
+
+    fn get_cursor_code(shape: CursorShape) -> &'static str {
+        match shape {
+            CursorShape::Block => "\x1b[2 q",
+            CursorShape::Bar => "\x1b[6 q",
+        }
+    }
+}
+
+impl Backend for TermiosBackend {
+    fn size(&mut self) -> Result<Position> {
+        write!(io::stdout(), "\x1b[999C\x1b[999B")?;
+        stdout().flush()?;
+        Self::get_cursor_pos()
+    }
+
+    fn read_key(&mut self) -> Result<u8> {
+        let mut buffer = [0; 4];
+        let bytes_read = io::stdin().read(&mut buffer)?;
+        if bytes_read == 0 {
+            return Ok(0);
+        }
+        Ok(if buffer[0] == b'\x1b' {
+            self.handle_other_keys(&buffer)
+        } else {
+            buffer[0]
+        })
+    }
+
+    fn scroll(&mut self, top: usize, bottom: usize, lines: isize) {
+        let height = bottom.saturating_sub(top);
+        let count = lines.unsigned_abs();
+        if lines == 0 || count >= height || self.drawn.len() < bottom * self.width {
+            return;
+        }
+        let region = &mut self.drawn[top * self.width..bottom * self.width];
+        if lines > 0 {
+            region.rotate_left(count * self.width);
+            region[(height - count) * self.width..].fill(None);
+        } else {
+            region.rotate_right(count * self.width);
+            region[..count * self.width].fill(None);
+        }
+        self.pending
+            .push_str(&format!("\x1b[{};{}r", top + 1, bottom));
+        let direction = if lines > 0 { 'S' } else { 'T' };
+        self.pending
+            .push_str(&format!("\x1b[{}{}", count, direction));
+        self.pending.push_str("\x1b[r");
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn.fill(None);
+    }
+
+    // writes the cells that differ from the last frame
+    fn draw(
+        &mut self,
+        screen: &Screen,
+        theme: &Theme,
+        cursor: &Position,
+        shape: CursorShape,
+    ) -> Result<()> {
+        if self.width != screen.width || self.drawn.len() != screen.width * screen.height {
+            self.width = screen.width;
+            self.drawn = vec![None; screen.width * screen.height];
+            self.pending.clear();
+        }
+        let mut abuf = String::from("\x1b[?2026h\x1b[?25l");
+        abuf.push_str(&self.pending);
+        self.pending.clear();
+        let mut style = None;
+        let mut at = None;
+        for y in 0..screen.height {
+            for x in 0..screen.width {
+                let cell = screen.get(x, y);
+                let drawn = &mut self.drawn[y * screen.width + x];
+                if *drawn == Some(cell) {
+                    continue;
+                }
+                if at != Some((x, y)) {
+                    abuf.push_str(&format!("\x1b[{};{}H", y + 1, x + 1));
+                }
+                if style != Some((cell.fg, cell.bg)) {
+                    abuf.push_str(&theme.fg(cell.fg));
+                    abuf.push_str(&theme.bg(cell.bg));
+                    style = Some((cell.fg, cell.bg));
+                }
+                abuf.push(cell.ch);
+                *drawn = Some(cell);
+                at = Some((x + 1, y));
+            }
+        }
+        abuf.push_str(Self::get_cursor_code(shape));
+        abuf.push_str(&format!("\x1b[{};{}H", cursor.y + 1, cursor.x + 1));
+        abuf.push_str("\x1b[?25h\x1b[?2026l");
+        write!(io::stdout(), "{}", abuf)?;
+        stdout().flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for TermiosBackend {
+    fn drop(&mut self) {
+        tcsetattr(io::stdin().as_raw_fd(), TCSAFLUSH, &self.termios).expect("tcsetattr");
+        write!(io::stdout(), "\x1b[?1049l").expect("write");
+        stdout().flush().expect("flush");
+    }
+}
@@ -1,8 +1,15 @@
 use crate::{
     error::{AppError, FileError},
-    file::{load_file, write_file_to_disk},
+    file::{
+        BackupCopy, FileFormat, FileLayout, FileLoader, WriteOptions, hash_file, is_writable,
+        load_file, load_hex, load_lossy, map_file, write_file_to_disk,
+    },
     insertmode::InsertType,
     normalmode::motions::Motion,
+    options::{self, OptionValue, Options},
+    rope::Rope,
+    swap::SwapFile,
+    syntax::{FileType, Highlighter},
     terminal::Position,
 };
 use std::{
@@ -12,12 +19,37 @@ use std::{
 };
 
 pub struct TextBuffer {
-    pub filename: Option<String>,
-    pub modified_time: Duration,
-    pub rows: Vec<String>,
-    pub pos: Position,
+    pub(crate) filename: Option<String>,
+    pub(crate) modified_time: Duration,
+    pub(crate) rows: Rope,
+    pub(crate) pos: Position,
     x_end: usize,
-    pub is_changed: bool,
+    pub(crate) is_changed: bool,
+    pub(crate) options: Options,
+    pub(crate) syntax: Highlighter,
+    loader: Option<FileLoader>,
+    // at least `largefile` MiB when opened, highlighting stays off
+    large: bool,
+    pub(crate) swap: Option<SwapFile>,
+    // why the file could not be read, taken by the editor to report it
+    pub(crate) open_error: Option<FileError>,
+    // the first line of the file that is not UTF-8, what the buffer holds is never written
+    // back over that file
+    invalid_line: Option<usize>,
+    // size and contents of the file when it was read or written, to tell a touched file from
+    // a changed one, no hash is kept for large files
+    disk_len: Option<u64>,
+    disk_hash: Option<u64>,
+    // the modification time last looked at by check_disk
+    checked_time: Duration,
+}
+
+// what check_disk found about the buffer's file
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DiskState {
+    Same,
+    Changed,
+    Deleted,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -28,22 +60,23 @@ pub enum CharClass {
 }
 
 impl TextBuffer {
-    pub fn load_buffers(
-        args: Vec<String>,
+    pub(crate) fn load_buffers(
+        files: Vec<String>,
         buff_vec: &mut Vec<usize>,
+        options: &Options,
     ) -> Result<HashMap<usize, TextBuffer>, AppError> {
         let mut count: usize = 1000;
         let mut buffers = HashMap::new();
 
-        if args.len() > 1 {
-            for filename in args.iter().skip(1) {
-                let buffer = TextBuffer::new(Some(filename.clone()))?;
+        if !files.is_empty() {
+            for filename in files.iter() {
+                let buffer = TextBuffer::new(Some(filename.clone()), options)?;
                 buffers.insert(count, buffer);
                 buff_vec.push(count);
                 count += 1;
             }
         } else {
-            let buffer = Self::create_empty_buffer().unwrap();
+            let buffer = TextBuffer::new(None, options)?;
             buff_vec.push(count);
             buffers.insert(count, buffer);
         }
@@ -70,7 +103,7 @@ impl TextBuffer {
         modified_time
     }
 
-    fn create_empty_buffer() -> Result<TextBuffer, AppError> {
+    fn create_empty_buffer(options: &Options) -> Result<TextBuffer, AppError> {
         let now = SystemTime::now();
         let modified_time = now.duration_since(UNIX_EPOCH).unwrap();
         Ok(TextBuffer {
@@ -78,61 +111,498 @@ impl TextBuffer {
             filename: None,
             modified_time,
             x_end: 0,
-            rows: Vec::new(),
+            rows: Rope::new(),
             pos: Position::new(),
+            options: options.local_copy(),
+            syntax: Highlighter::default(),
+            loader: None,
+            large: false,
+            swap: None,
+            open_error: None,
+            invalid_line: None,
+            disk_len: None,
+            disk_hash: None,
+            checked_time: modified_time,
         })
     }
 
-    fn create_file_buffer(filename: String) -> Result<TextBuffer, AppError> {
+    fn create_file_buffer(filename: String, options: &Options) -> Result<TextBuffer, AppError> {
         let modified_time = Self::get_modified_time(&filename);
         let pos = Position::new();
-        let rows = load_file(&filename)?;
-        Ok(Self {
+        let size = fs::metadata(&filename).map_or(0, |metadata| metadata.len());
+        let largefile = options.get_number("largefile") as u64;
+        let large = largefile > 0 && size >= largefile * 1024 * 1024;
+        let loaded = if large {
+            map_file(&filename).map(|(rows, layout, loader)| (rows, Some(layout), loader, None))
+        } else {
+            load_file(&filename).map(|(rows, layout, hash)| (rows, layout, None, Some(hash)))
+        };
+        // a file that cannot be read opens empty and read-only, so it is not overwritten
+        let (rows, layout, loader, hash, open_error) = match loaded {
+            Ok((rows, layout, loader, hash)) => (rows, layout, loader, hash, None),
+            Err(e) => (Rope::new(), None, None, None, Some(e)),
+        };
+        let invalid_line = match &open_error {
+            Some(FileError::InvalidUtf8(_, line)) => Some(*line),
+            _ => None,
+        };
+        let mut buffer = Self {
             is_changed: false,
             modified_time,
             filename: Some(filename),
             x_end: 0,
             rows,
             pos,
-        })
+            options: options.local_copy(),
+            syntax: Highlighter::default(),
+            loader,
+            large,
+            swap: None,
+            open_error,
+            invalid_line,
+            disk_len: None,
+            disk_hash: None,
+            checked_time: modified_time,
+        };
+        buffer.record_disk(hash);
+        if let Some(layout) = layout {
+            buffer.set_layout(&layout);
+        }
+        // kept read-only when it could not be read, or cannot be written by this user
+        if buffer.open_error.is_some() || !is_writable(buffer.filename.as_deref().unwrap()) {
+            buffer.set_option("readonly", OptionValue::Bool(true));
+        }
+        Ok(buffer)
     }
 
-    pub fn new(filename: Option<String>) -> Result<Self, AppError> {
+    pub(crate) fn new(filename: Option<String>, options: &Options) -> Result<Self, AppError> {
         match filename {
-            None => Self::create_empty_buffer(),
-            Some(name) => Self::create_file_buffer(name),
+            None => Self::create_empty_buffer(options),
+            Some(name) => Self::create_file_buffer(name, options),
         }
     }
 
-    pub fn insert_char(&mut self, c: u8) {
-        if self.rows.is_empty() {
-            let row: String;
-            if c == 9 {
-                row = String::from("    ");
-                self.pos.x += 4;
+    // remembers the file as it is now on disk, with the hash of the bytes just read or written
+    fn record_disk(&mut self, hash: Option<u64>) {
+        let Some(name) = self.filename.clone() else {
+            return;
+        };
+        self.modified_time = Self::get_modified_time(&name);
+        self.checked_time = self.modified_time;
+        self.disk_len = fs::metadata(&name).ok().map(|metadata| metadata.len());
+        self.disk_hash = hash;
+    }
+
+    // whether the file on disk is not the one read or last written, a new modification time
+    // with the same size and contents is taken as the same file
+    fn disk_differs(&mut self) -> bool {
+        let Some(name) = self.filename.clone() else {
+            return false;
+        };
+        let Ok(metadata) = fs::metadata(&name) else {
+            return false;
+        };
+        let modified = Self::get_modified_time(&name);
+        if modified == self.modified_time {
+            return false;
+        }
+        // only a file of the same size is read again to compare its contents
+        if self.disk_len == Some(metadata.len())
+            && self.disk_hash.is_some()
+            && hash_file(&name) == self.disk_hash
+        {
+            self.modified_time = modified;
+            return false;
+        }
+        true
+    }
+
+    // a change on disk or the file being deleted is reported the first time it is seen, kept
+    // changes are not reported again until the file changes once more
+    pub fn check_disk(&mut self) -> DiskState {
+        let Some(name) = &self.filename else {
+            return DiskState::Same;
+        };
+        if fs::metadata(name).is_err() {
+            // a file that was there when read or written has been deleted
+            return match self.disk_len.take() {
+                Some(_) => DiskState::Deleted,
+                None => DiskState::Same,
+            };
+        }
+        let modified = Self::get_modified_time(name);
+        if modified == self.checked_time {
+            return DiskState::Same;
+        }
+        self.checked_time = modified;
+        if self.disk_differs() {
+            DiskState::Changed
+        } else {
+            DiskState::Same
+        }
+    }
+
+    // reads the file again, dropping any changes in the buffer
+    pub(crate) fn reload(&mut self, options: &Options) -> Result<(), FileError> {
+        let name = self.filename.clone().ok_or(FileError::EmptyFileName)?;
+        let mut fresh = Self::create_file_buffer(name, options).map_err(FileError::OtherError)?;
+        if let Some(e) = fresh.open_error.take() {
+            return Err(e);
+        }
+        for name in ["fileformat", "endofline", "bomb"] {
+            if let Some(value) = fresh.options.get(name).cloned() {
+                self.set_option(name, value);
+            }
+        }
+        self.rows = fresh.rows;
+        self.loader = fresh.loader.take();
+        self.large = fresh.large;
+        self.invalid_line = None;
+        self.pos.y = self.pos.y.min(self.rows.len().saturating_sub(1));
+        self.set_x_or(0, self.pos.x);
+        self.syntax.invalidate(0);
+        self.is_changed = false;
+        self.record_disk(fresh.disk_hash);
+        Ok(())
+    }
+
+    // names the buffer after another file, taken as it is on disk now so that it is not
+    // reported as changed
+    pub fn set_filename(&mut self, name: String) {
+        self.filename = Some(name);
+        self.invalid_line = None;
+        self.record_disk(None);
+    }
+
+    // puts the lines of filename below the cursor, returns how many
+    pub fn read_below(&mut self, filename: &str) -> Result<usize, FileError> {
+        let (rows, layout, _) = load_file(filename)?;
+        if layout.is_none() {
+            let e = std::io::Error::from(std::io::ErrorKind::NotFound);
+            return Err(FileError::Io(filename.to_string(), e));
+        }
+        if !self.is_modifiable() {
+            return Ok(0);
+        }
+        self.finish_loading();
+        let at = if self.rows.is_empty() {
+            0
+        } else {
+            self.pos.y + 1
+        };
+        for (i, line) in rows.iter().enumerate() {
+            self.rows.insert(at + i, line.to_string());
+        }
+        if !rows.is_empty() {
+            self.pos.y = at;
+            self.pos.x = 0;
+            self.syntax.invalidate(at);
+            self.is_changed = true;
+        }
+        Ok(rows.len())
+    }
+
+    // replaces pattern in the whole file or the cursor line, the first match of a line unless
+    // global, returns the number of matches and of lines replaced
+    pub fn substitute(
+        &mut self,
+        whole: bool,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> (usize, usize) {
+        if !self.is_modifiable() {
+            return (0, 0);
+        }
+        self.finish_loading();
+        let lines = if whole {
+            0..self.rows.len()
+        } else {
+            self.pos.y..self.pos.y + 1
+        };
+        let (mut count, mut changed, mut first, mut last) = (0, 0, None, 0);
+        for y in lines {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let matches = row.matches(pattern).count();
+            if matches == 0 {
+                continue;
+            }
+            let (line, replaced) = if global {
+                (row.replace(pattern, replacement), matches)
             } else {
-                row = String::from(c as char);
-                self.pos.x += 1;
+                (row.replacen(pattern, replacement, 1), 1)
+            };
+            if let Some(row) = self.rows.get_mut(y) {
+                *row = line;
             }
-            self.rows.push(row);
+            count += replaced;
+            changed += 1;
+            first.get_or_insert(y);
+            last = y;
+        }
+        if let Some(first) = first {
+            self.syntax.invalidate(first);
+            self.pos.y = last;
+            self.pos.x = self.first_non_white_space();
+            self.is_changed = true;
+        }
+        (count, changed)
+    }
+
+    // what `:file` shows: name, state, line count and how far through the file the cursor is
+    pub fn file_info(&self) -> String {
+        let mut info = format!("\"{}\"", self.filename.as_deref().unwrap_or("[No Name]"));
+        if self.is_changed {
+            info.push_str(" [Modified]");
+        }
+        if self.is_readonly() {
+            info.push_str(" [RO]");
+        }
+        let lines = self.rows.len();
+        if lines == 0 {
+            info.push_str(" --No lines in buffer--");
+        } else {
+            info.push_str(&format!(
+                " {} line{} --{}%--",
+                lines,
+                if lines == 1 { "" } else { "s" },
+                (self.pos.y + 1) * 100 / lines
+            ));
+        }
+        info
+    }
+
+    // writes of the file itself need `!`
+    pub fn is_readonly(&self) -> bool {
+        self.options.get_bool("readonly")
+    }
+
+    // with nomodifiable every edit is refused, not only writes
+    pub fn is_modifiable(&self) -> bool {
+        self.options.get_bool("modifiable")
+    }
+
+    fn set_option(&mut self, name: &str, value: OptionValue) {
+        if let Some(def) = options::find(name) {
+            self.options.set(def, value);
+        }
+    }
+
+    fn set_layout(&mut self, layout: &FileLayout) {
+        self.set_option(
+            "fileformat",
+            OptionValue::String(layout.format.name().to_string()),
+        );
+        self.set_option("endofline", OptionValue::Bool(layout.eol));
+        self.set_option("bomb", OptionValue::Bool(layout.bom));
+    }
+
+    // how the buffer is written, fixendofline always adds the final line break
+    fn layout(&self) -> FileLayout {
+        FileLayout {
+            format: FileFormat::from_name(&self.options.get_string("fileformat"))
+                .unwrap_or_default(),
+            eol: self.options.get_bool("endofline") || self.options.get_bool("fixendofline"),
+            bom: self.options.get_bool("bomb"),
+        }
+    }
+
+    // picks up lines a background load has indexed since the last call
+    pub fn poll_loader(&mut self) {
+        if let Some(loader) = &mut self.loader
+            && !loader.poll(&mut self.rows)
+        {
+            self.end_loading();
+        }
+    }
+
+    // percentage of the file read while it is still loading
+    pub fn load_progress(&self) -> Option<usize> {
+        self.loader.as_ref().map(|loader| loader.progress())
+    }
+
+    fn finish_loading(&mut self) {
+        if let Some(loader) = &mut self.loader {
+            loader.finish(&mut self.rows);
+            self.end_loading();
+        }
+    }
+
+    // a file found not to be UTF-8 while loading keeps the lines before it and any changes
+    // made to them, turns read-only and gets the error for the editor to report
+    fn end_loading(&mut self) {
+        let Some(loader) = self.loader.take() else {
+            return;
+        };
+        let (Some(line), Some(name)) = (loader.invalid(), self.filename.clone()) else {
+            return;
+        };
+        self.invalid_line = Some(line);
+        self.open_error = Some(FileError::InvalidUtf8(name, line));
+        self.set_option("readonly", OptionValue::Bool(true));
+    }
+
+    // reloads a file that is not valid UTF-8 with the bad bytes replaced, or as a hex dump
+    pub fn load_view(&mut self, hex: bool) -> Result<(), FileError> {
+        let name = self.filename.clone().ok_or(FileError::EmptyFileName)?;
+        if hex {
+            self.rows = load_hex(&name)?;
+            self.syntax = Highlighter::default();
+        } else {
+            let (rows, layout) = load_lossy(&name)?;
+            self.rows = rows;
+            self.set_layout(&layout);
+        }
+        self.pos = Position::new();
+        self.syntax.invalidate(0);
+        self.is_changed = false;
+        self.set_option("readonly", OptionValue::Bool(true));
+        Ok(())
+    }
+
+    // writes the swap file in the background when the text or cursor moved since the last sync
+    pub fn sync_swap(&mut self) {
+        if let Some(swap) = &mut self.swap {
+            swap.sync(&self.rows, self.is_changed, self.pos);
+        }
+    }
+
+    // pos moved onto the text, for a cursor kept elsewhere while the buffer changed
+    pub fn clamp(&self, pos: Position) -> Position {
+        let y = pos.y.min(self.rows.len().saturating_sub(1));
+        let line = self.rows.get(y).unwrap_or_default();
+        let mut x = pos.x.min(line.len().saturating_sub(1));
+        while !line.is_char_boundary(x) {
+            x -= 1;
+        }
+        Position { x, y }
+    }
+
+    // replaces the text with lines recovered from a swap file
+    pub fn restore(&mut self, rows: Rope, pos: Position) {
+        self.loader = None;
+        self.rows = rows;
+        self.pos.y = pos.y.min(self.rows.len().saturating_sub(1));
+        self.set_x_or(0, pos.x);
+        self.syntax.invalidate(0);
+        self.is_changed = true;
+    }
+
+    /// The name of the file the buffer is written to, if it has one.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// All lines of the buffer.
+    pub fn rows(&self) -> &Rope {
+        &self.rows
+    }
+
+    /// Where the cursor is, as a 0 based line and byte offset.
+    pub fn cursor(&self) -> Position {
+        self.pos
+    }
+
+    /// True when the buffer has changes that have not been written.
+    pub fn is_modified(&self) -> bool {
+        self.is_changed
+    }
+
+    pub fn line(&self, y: usize) -> Option<&str> {
+        self.rows.get(y)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn insert_char(&mut self, c: u8) {
+        let text = if c == 9 {
+            self.tab_string()
+        } else {
+            String::from(c as char)
+        };
+        self.insert_text(&text);
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        if !self.is_modifiable() {
             return;
         }
+        if self.rows.is_empty() {
+            self.rows.push(String::new());
+        }
         if let Some(row) = self.rows.get_mut(self.pos.y) {
-            if c == 9 {
-                row.insert_str(self.pos.x, "    ");
-                self.pos.x += 4;
-            } else {
-                row.insert(self.pos.x, c as char);
-                self.pos.x += 1;
-            }
+            row.insert_str(self.pos.x, text);
+            self.pos.x += text.len();
+        }
+        self.syntax.invalidate(self.pos.y);
+    }
+
+    pub fn word_before_cursor(&self) -> Option<&str> {
+        let line = self.get_current_line()?.get(..self.pos.x)?;
+        let start = line
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| Self::find_char_class(*c) == CharClass::Keyword)
+            .last()
+            .map(|(i, _)| i)?;
+        Some(&line[start..])
+    }
+
+    pub fn replace_before_cursor(&mut self, len: usize, text: &str) {
+        if !self.is_modifiable() {
+            return;
+        }
+        let start = self.pos.x.saturating_sub(len);
+        if let Some(row) = self.rows.get_mut(self.pos.y) {
+            row.replace_range(start..self.pos.x, text);
+            self.pos.x = start + text.len();
         }
+        self.syntax.invalidate(self.pos.y);
+    }
+
+    pub fn detect_filetype(&mut self) {
+        let filetype = FileType::detect(self.filename.as_deref(), self.rows.first());
+        let name = filetype.map_or("", |filetype| filetype.name());
+        self.set_option("filetype", OptionValue::String(name.to_string()));
+        self.syntax = Highlighter::new(filetype.filter(|_| !self.large));
+    }
+
+    pub fn set_filetype(&mut self, name: &str) {
+        self.syntax = Highlighter::new(FileType::from_name(name).filter(|_| !self.large));
+    }
+
+    fn tab_string(&self) -> String {
+        if !self.options.get_bool("expandtab") {
+            return String::from("\t");
+        }
+        let width = match self.options.get_number("softtabstop") {
+            0 => self.options.get_number("tabstop"),
+            n => n,
+        };
+        let col = self
+            .get_current_line()
+            .map_or(self.pos.x, |line| self.display_col(line, self.pos.x));
+        " ".repeat(width - col % width)
+    }
+
+    // the screen column of byte offset x, tabs expanded to the next tabstop like on screen
+    fn display_col(&self, line: &str, x: usize) -> usize {
+        let tabstop = self.options.get_number("tabstop").max(1);
+        line[..x.min(line.len())].chars().fold(0, |col, c| match c {
+            '\t' => col + tabstop - col % tabstop,
+            _ => col + 1,
+        })
     }
 
     fn is_valid_y(&self, y: usize) -> bool {
         y < self.rows.len()
     }
 
-    fn get_current_line(&self) -> Option<&String> {
+    fn get_current_line(&self) -> Option<&str> {
         self.rows.get(self.pos.y)
     }
 
@@ -160,6 +630,10 @@ impl TextBuffer {
     }
 
     pub fn split_line(&mut self) {
+        if !self.is_modifiable() {
+            return;
+        }
+        self.syntax.invalidate(self.pos.y);
         if self.rows.is_empty() {
             self.rows.push(String::new());
             self.pos.y += 1;
@@ -218,7 +692,7 @@ impl TextBuffer {
         if end > self.rows.len() {
             end = self.rows.len();
         }
-        self.rows.drain(start..end);
+        self.rows.remove_range(start..end);
         self.set_y_or(self.end_of_file(), self.pos.y);
         self.set_x_or(0, self.pos.x);
     }
@@ -274,11 +748,11 @@ impl TextBuffer {
         if !self.is_valid_y(self.pos.y) || !self.is_valid_y(self.pos.y.saturating_sub(1)) {
             return;
         }
-        let addingline = self.rows.get(self.pos.y).unwrap().clone();
+        let addingline = self.rows.get(self.pos.y).unwrap().to_string();
         self.rows
             .get_mut(self.pos.y.saturating_sub(1))
             .unwrap()
-            .push_str(addingline.as_str());
+            .push_str(&addingline);
         self.delete_lines(self.pos.y, self.pos.y + 1);
     }
 
@@ -387,8 +861,7 @@ impl TextBuffer {
 
     fn get_next_empty_string(&self) -> usize {
         self.rows
-            .iter()
-            .skip(self.pos.y + 1)
+            .iter_from(self.pos.y + 1)
             .enumerate()
             .find(|(_, s)| s.is_empty())
             .map_or(self.rows.len().saturating_sub(1), |(idx, _)| {
@@ -397,13 +870,10 @@ impl TextBuffer {
     }
 
     fn get_previous_empty_string(&self) -> usize {
-        self.rows
-            .iter()
-            .take(self.pos.y)
-            .enumerate()
+        (0..self.pos.y)
             .rev()
-            .find(|(_, s)| s.is_empty())
-            .map_or(0, |(idx, _)| idx)
+            .find(|&y| self.rows[y].is_empty())
+            .unwrap_or(0)
     }
 
     fn move_previous_paragraph(&mut self, repeat: usize) {
@@ -451,22 +921,16 @@ impl TextBuffer {
             self.delete_lines(start_line, end_line);
             self.move_to_line(start_line + 1);
             self.delete_str(0, end);
-            let addingline = self.rows.get(end_line).unwrap().clone();
-            self.rows
-                .get_mut(start_line)
-                .unwrap()
-                .push_str(addingline.as_str());
+            let addingline = self.rows.get(end_line).unwrap().to_string();
+            self.rows.get_mut(start_line).unwrap().push_str(&addingline);
             self.delete_lines(start_line + 1, start_line + 2);
         } else {
             self.delete_str(start, self.end_of_line() + 1);
             self.delete_lines(start_line + 1, end_line);
             self.move_to_line(start_line + 1);
             self.delete_str(0, end);
-            let addingline = self.rows.get(end_line).unwrap().clone();
-            self.rows
-                .get_mut(start_line)
-                .unwrap()
-                .push_str(addingline.as_str());
+            let addingline = self.rows.get(end_line).unwrap().to_string();
+            self.rows.get_mut(start_line).unwrap().push_str(&addingline);
             self.delete_lines(start_line + 1, start_line + 2);
         }
     }
@@ -479,11 +943,10 @@ impl TextBuffer {
                 .unwrap()
                 .chars()
                 .nth(self.pos.x + word)
+                && val.is_whitespace()
             {
-                if val.is_whitespace() {
-                    self.move_to_x(word);
-                    word = self.get_next_word();
-                }
+                self.move_to_x(word);
+                word = self.get_next_word();
             }
 
             if self.rows[self.pos.y].len() == word {
@@ -519,9 +982,9 @@ impl TextBuffer {
             Some(val) => val,
             None => return 0,
         };
-        let mut initial_type = self.find_char_class(initial_char);
+        let mut initial_type = Self::find_char_class(initial_char);
         for (i, c) in curr_line.char_indices().skip(self.pos.x) {
-            let char_type = self.find_char_class(c);
+            let char_type = Self::find_char_class(c);
             if char_type != initial_type {
                 if char_type == CharClass::WhiteSpace {
                     initial_type = CharClass::WhiteSpace;
@@ -552,7 +1015,7 @@ impl TextBuffer {
         curr_line.len()
     }
 
-    fn find_char_class(&self, c: char) -> CharClass {
+    pub fn find_char_class(c: char) -> CharClass {
         match c {
             c if c.is_whitespace() => CharClass::WhiteSpace,
             c if c.is_alphanumeric() || c == '_' => CharClass::Keyword,
@@ -575,7 +1038,10 @@ impl TextBuffer {
             Motion::EndOfLine(repeat) => self.move_to_end_of_line(repeat),
             Motion::StartOfNonWhiteSpace => self.move_to_first_non_white_space(),
             Motion::GoToLine(line) => self.move_to_line(line),
-            Motion::EndOfFile => self.move_to_line(self.end_of_file()),
+            Motion::EndOfFile => {
+                self.finish_loading();
+                self.move_to_line(self.end_of_file())
+            }
         }
     }
 
@@ -613,7 +1079,11 @@ impl TextBuffer {
     }
 
     pub fn insert(&mut self, pos: InsertType) {
+        if !self.is_modifiable() {
+            return;
+        }
         self.is_changed = true;
+        self.syntax.invalidate(self.pos.y);
         match pos {
             InsertType::Append => self.insert_append(self.pos.x + 1),
             InsertType::InsertStart => self.move_to_first_non_white_space(),
@@ -625,7 +1095,11 @@ impl TextBuffer {
     }
 
     pub fn delete(&mut self, direction: Motion) {
+        if !self.is_modifiable() {
+            return;
+        }
         self.is_changed = true;
+        self.syntax.invalidate(self.pos.y.saturating_sub(1));
         match direction {
             Motion::Left(repeat) => self.delete_left(repeat),
             Motion::Right(repeat) => self.delete_right(repeat),
@@ -636,7 +1110,10 @@ impl TextBuffer {
             Motion::EndOfLine(repeat) => self.delete_to_end_of_line(repeat),
             Motion::StartOfNonWhiteSpace => self.delete_to_first_non_white_space(),
             Motion::StartOfLine => self.delete_start_of_line(),
-            Motion::EndOfFile => self.delete_lines(self.pos.y, self.end_of_file() + 1),
+            Motion::EndOfFile => {
+                self.finish_loading();
+                self.delete_lines(self.pos.y, self.end_of_file() + 1)
+            }
             _ => (),
         }
     }
@@ -649,32 +1126,81 @@ impl TextBuffer {
         self.set_x_or(self.end_of_line(), self.pos.x);
     }
 
-    pub fn write_buffer_file(
+    // writes to filename, which then becomes the buffer's file
+    pub(crate) fn save_as(
+        &mut self,
+        filename: String,
+        options: &Options,
+    ) -> Result<String, FileError> {
+        self.prepare_write(Some(&filename))?;
+        let hash = self.write_to(&filename, options)?;
+        self.filename = Some(filename.clone());
+        self.invalid_line = None;
+        self.is_changed = false;
+        self.record_disk(Some(hash));
+        Ok(filename)
+    }
+
+    pub(crate) fn write_buffer_file(
         &mut self,
         force: bool,
         filename: Option<String>,
+        options: &Options,
     ) -> Result<String, FileError> {
+        self.prepare_write(filename.as_deref())?;
         if let Some(name) = filename {
-            write_file_to_disk(&name, &self.rows).map_err(FileError::OtherError)?;
-            self.modified_time = Self::get_modified_time(&name);
+            let hash = self.write_to(&name, options)?;
             self.is_changed = false;
             if self.filename.is_none() {
                 self.filename = Some(name.clone())
             }
+            if self.filename.as_ref() == Some(&name) {
+                self.record_disk(Some(hash));
+            }
             Ok(name)
         } else if let Some(name) = self.filename.clone() {
-            if !force {
-                let modified_time = Self::get_modified_time(&name);
-                if modified_time != self.modified_time {
-                    return Err(FileError::FileChanged);
-                }
+            if !force && self.disk_differs() {
+                return Err(FileError::FileChanged);
             };
-            write_file_to_disk(&name, &self.rows).map_err(FileError::OtherError)?;
-            self.modified_time = Self::get_modified_time(&name);
+            let hash = self.write_to(&name, options)?;
+            self.record_disk(Some(hash));
             self.is_changed = false;
-            return Ok(name);
+            Ok(name)
         } else {
-            return Err(FileError::EmptyFileName);
+            Err(FileError::EmptyFileName)
         }
     }
-}
\ No newline at end of file
+
+    // loads the rest of the file before writing, which filename (the buffer's file when None)
+    // must not be when the file is not UTF-8
+    fn prepare_write(&mut self, filename: Option<&str>) -> Result<(), FileError> {
+        self.finish_loading();
+        // a swap write may still be reading mapped lines of the file about to be overwritten
+        if self.rows.is_mapped()
+            && let Some(swap) = &mut self.swap
+        {
+            swap.wait();
+        }
+        // not even `!` writes over a file that is not UTF-8
+        if let Some(line) = self.invalid_line
+            && let Some(name) = self.filename.clone()
+            && filename.is_none_or(|filename| filename == name)
+        {
+            return Err(FileError::InvalidUtf8(name, line));
+        }
+        Ok(())
+    }
+
+    // writes the rows to name, returns the hash of what was written
+    fn write_to(&mut self, name: &str, options: &Options) -> Result<u64, FileError> {
+        let write = WriteOptions {
+            backup: options
+                .get_bool("backup")
+                .then(|| options.get_string("backupext")),
+            copy: BackupCopy::from_name(&options.get_string("backupcopy")).unwrap_or_default(),
+        };
+        let layout = self.layout();
+        write_file_to_disk(name, &mut self.rows, &layout, &write)
+            .map_err(|e| FileError::Io(name.to_string(), e))
+    }
+}
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 
 use crate::error::FileError;
+use crate::keymap::MapMode;
 #[derive(Default)]
 pub struct CommandMode {
     pub command_string: String,
@@ -10,6 +11,11 @@ pub struct CommandMode {
 pub enum CommandReturn {
     BuffNext,
     BuffPrev,
+    BuffAlternate,
+    BuffName(String),
+    BuffList,
+    // name, force, wipe
+    BuffDelete(Option<String>, bool, bool),
     Escape,
     SaveQuit(Option<String>),
     BuffN(usize),
@@ -17,7 +23,57 @@ pub enum CommandReturn {
     Save(Option<String>),
     Quit,
     ForceQuit,
+    QuitAll,
+    ForceQuitAll,
+    SaveAll,
+    SaveQuitAll,
+    // `:x`: write only when there are changes, then quit
+    Exit(Option<String>),
     ForceSave(Option<String>),
+    Set(String),
+    SetLocal(String),
+    Source(Option<String>),
+    AutoCmd(String),
+    AutoCmdClear(String),
+    Map(Vec<MapMode>, bool, String),
+    Unmap(Vec<MapMode>, String),
+    Abbrev(String),
+    Unabbrev(String),
+    AbClear,
+    ColorScheme(String),
+    Recover,
+    CheckTime,
+    Edit(Option<String>),
+    ForceEdit(Option<String>),
+    Enew,
+    SaveAs(Option<String>),
+    ForceSaveAs(Option<String>),
+    Read(Option<String>),
+    File(Option<String>),
+    Args(String),
+    ArgNext,
+    ArgPrev,
+    ArgFirst,
+    ArgLast,
+    ArgAdd(String),
+    ArgDo(String),
+    BufDo(String),
+    Update,
+    // the whole file or the cursor line, pattern, replacement, every match on a line
+    Substitute(bool, String, String, bool, bool),
+    // vertical, and the file to edit in the new window
+    Split(bool, Option<String>),
+    Close,
+    Only,
+    TabNew(Option<String>),
+    TabClose,
+    // the tab page number, or 0 for the next one
+    TabNext(usize),
+    TabPrev(usize),
+    TabMove(Option<String>),
+    // `first | rest`, run one after the other
+    Bar(String, String),
+    Error(String),
     None,
 }
 
@@ -31,21 +87,27 @@ impl CommandMode {
         }
     }
 
-    pub fn handle_file_write_result(&mut self, result: Result<String, FileError>) {
+    pub fn handle_file_write_result(
+        &mut self,
+        result: Result<String, FileError>,
+    ) -> Result<(), String> {
         self.escape("");
         match result {
-            Ok(filename) => self.command_string = format!("saved file {}", filename),
-            Err(e) => self.handle_file_error(e),
+            Ok(filename) => {
+                self.command_string = format!("saved file {}", filename);
+                Ok(())
+            }
+            Err(e) => Err(self.handle_file_error(e)),
         }
     }
 
-    fn handle_file_error(&mut self, e: FileError) {
+    fn handle_file_error(&mut self, e: FileError) -> String {
         match e {
-            FileError::EmptyFileName => self.command_string = String::from("Empty file name"),
-            FileError::OtherError(_) => (),
-            FileError::FileChanged => {
-                self.command_string = String::from("file changed use w! to overwrite ")
-            }
+            FileError::EmptyFileName => String::from("Empty file name"),
+            FileError::OtherError(e) => e.to_string(),
+            FileError::FileChanged => String::from("file changed use w! to overwrite "),
+            FileError::Io(..) => format!("Can't write {}", e),
+            FileError::InvalidUtf8(..) => e.to_string(),
         }
     }
 
@@ -53,18 +115,11 @@ impl CommandMode {
         if c == b'\x1b' {
             return CommandReturn::Escape;
         }
-        if c == b':' {
+        if c == b':' && self.command_string.is_empty() {
             return CommandReturn::None;
         }
         if c == 13 {
-            if self.command.is_empty() {
-                self.command = self.command_string.clone();
-            } else {
-                let mut words = self.command_string.split_whitespace();
-                words.next();
-                self.value = words.next().map(|s| s.to_string());
-            }
-            return self.execute();
+            return self.parse(&self.command_string.clone());
         }
         if c == 127 {
             self.command_string.pop();
@@ -77,8 +132,8 @@ impl CommandMode {
             Ordering::Equal => {
                 if self.command.is_empty() {
                     self.command = self.command_string.clone();
-                    self.command_string.push(c as char);
                 }
+                self.command_string.push(c as char);
             }
             Ordering::Greater => {
                 self.command_string.push(c as char);
@@ -90,14 +145,32 @@ impl CommandMode {
     }
 
     fn buffer_command(&mut self) -> CommandReturn {
-        let val = self.command.split_off(1);
+        match self.command.as_str() {
+            "bd" | "bdelete" | "bd!" | "bdelete!" | "bw" | "bwipeout" | "bw!" | "bwipeout!" => {
+                let force = self.command.ends_with('!');
+                let wipe = self.command.starts_with("bw");
+                return CommandReturn::BuffDelete(self.value.clone(), force, wipe);
+            }
+            "buffers" => return CommandReturn::BuffList,
+            "bufdo" | "bufd" => return CommandReturn::BufDo(self.args()),
+            "buffer" => self.command = String::from("b"),
+            _ => (),
+        }
+        // `:b name` takes a name, `:bname` is not a command
+        let bare = self.command == "b";
+        let mut val = self.command.split_off(1);
+        if val.is_empty() {
+            val = self.value.clone().unwrap_or_default();
+        }
         if let Ok(num) = val.as_str().parse::<usize>() {
             return CommandReturn::BuffN(num);
         }
         match val.as_str() {
-            "n" => CommandReturn::BuffNext,
-            "p" => CommandReturn::BuffPrev,
-            _ => CommandReturn::Escape,
+            "n" | "next" => CommandReturn::BuffNext,
+            "p" | "prev" | "previous" => CommandReturn::BuffPrev,
+            "#" => CommandReturn::BuffAlternate,
+            _ if bare && !val.is_empty() => CommandReturn::BuffName(val),
+            _ => CommandReturn::Error(format!("Not an editor command: {}", self.command_string)),
         }
     }
 
@@ -106,6 +179,100 @@ impl CommandMode {
         self.command_string = String::from(err);
     }
 
+    pub fn parse(&mut self, line: &str) -> CommandReturn {
+        if let Some((first, rest)) = Self::split_bar(line) {
+            return CommandReturn::Bar(first.to_string(), rest.to_string());
+        }
+        self.command_string = line.trim().to_string();
+        let mut words = self.command_string.split_whitespace();
+        self.command = words.next().unwrap_or_default().to_string();
+        self.value = words.next().map(|s| s.to_string());
+        self.execute()
+    }
+
+    // commands that run a command line of their own keep the `|` in it
+    fn split_bar(line: &str) -> Option<(&str, &str)> {
+        let name = line.split_whitespace().next()?;
+        if matches!(
+            name,
+            "argdo" | "bufdo" | "bufd" | "autocmd" | "au" | "autocmd!" | "au!"
+        ) {
+            return None;
+        }
+        let line = line.trim();
+        // a `|` in the pattern or replacement of `:s` is part of them
+        let start = match Self::substitute_start(line) {
+            Some((_, delimiter, rest)) => {
+                let skipped: usize = rest
+                    .splitn(3, delimiter)
+                    .take(2)
+                    .map(|part| part.len() + delimiter.len_utf8())
+                    .sum();
+                line.len() - rest.len() + skipped.min(rest.len())
+            }
+            None => 0,
+        };
+        let bar = start + line[start..].find('|')?;
+        Some((line[..bar].trim(), line[bar + 1..].trim()))
+    }
+
+    // whether a `:s` line is for every line, its delimiter and what follows that
+    fn substitute_start(line: &str) -> Option<(bool, char, &str)> {
+        let (whole, line) = match line.strip_prefix('%') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let rest = line
+            .strip_prefix("substitute")
+            .or_else(|| line.strip_prefix("s"))?;
+        let delimiter = rest
+            .chars()
+            .next()
+            .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && *c != '"')?;
+        Some((whole, delimiter, &rest[delimiter.len_utf8()..]))
+    }
+
+    // `:s/pattern/replacement/ge`, `%` in front for every line, the pattern is plain text,
+    // `g` replaces every match in a line and `e` makes no match at all not an error
+    fn substitute(&self) -> Option<CommandReturn> {
+        let (whole, delimiter, rest) = Self::substitute_start(&self.command_string)?;
+        let mut parts = rest.splitn(3, delimiter);
+        let pattern = parts.next().unwrap_or_default().to_string();
+        let replacement = parts.next().unwrap_or_default().to_string();
+        let flags = parts.next().unwrap_or_default().trim();
+        let (mut global, mut quiet) = (false, false);
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'e' => quiet = true,
+                _ => return Some(CommandReturn::Error(format!("Invalid flags: {}", flags))),
+            }
+        }
+        if pattern.is_empty() {
+            return Some(CommandReturn::Error(String::from("Empty search pattern")));
+        }
+        Some(CommandReturn::Substitute(
+            whole,
+            pattern,
+            replacement,
+            global,
+            quiet,
+        ))
+    }
+
+    fn count(&self) -> usize {
+        self.value
+            .as_deref()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn args(&self) -> String {
+        self.command_string
+            .split_once(' ')
+            .map_or(String::new(), |(_, args)| args.trim().to_string())
+    }
+
     fn execute(&mut self) -> CommandReturn {
         if self.command.starts_with("b") {
             return self.buffer_command();
@@ -117,10 +284,72 @@ impl CommandMode {
             "q" => CommandReturn::Quit,
             "wq" => CommandReturn::SaveQuit(self.value.clone()),
             "wq!" => CommandReturn::ForceSaveQuit(self.value.clone()),
-            _ => {
-                self.command_string = String::from("Invalild Command");
-                CommandReturn::Escape
+            "x" | "xit" | "exi" | "exit" => CommandReturn::Exit(self.value.clone()),
+            "qa" | "qall" | "quita" | "quitall" => CommandReturn::QuitAll,
+            "qa!" | "qall!" | "quita!" | "quitall!" => CommandReturn::ForceQuitAll,
+            "wa" | "wall" => CommandReturn::SaveAll,
+            "wqa" | "wqall" | "xa" | "xall" => CommandReturn::SaveQuitAll,
+            "set" | "se" => CommandReturn::Set(self.args()),
+            "setlocal" | "setl" => CommandReturn::SetLocal(self.args()),
+            "source" | "so" => CommandReturn::Source(self.value.clone()),
+            "autocmd" | "au" => CommandReturn::AutoCmd(self.args()),
+            "autocmd!" | "au!" => CommandReturn::AutoCmdClear(self.args()),
+            "map" | "nmap" | "nm" => CommandReturn::Map(vec![MapMode::Normal], false, self.args()),
+            "noremap" | "no" | "nnoremap" | "nn" => {
+                CommandReturn::Map(vec![MapMode::Normal], true, self.args())
+            }
+            "imap" | "im" => CommandReturn::Map(vec![MapMode::Insert], false, self.args()),
+            "inoremap" | "ino" => CommandReturn::Map(vec![MapMode::Insert], true, self.args()),
+            "cmap" | "cm" => CommandReturn::Map(vec![MapMode::Command], false, self.args()),
+            "cnoremap" | "cno" => CommandReturn::Map(vec![MapMode::Command], true, self.args()),
+            "map!" => {
+                CommandReturn::Map(vec![MapMode::Insert, MapMode::Command], false, self.args())
+            }
+            "noremap!" | "no!" => {
+                CommandReturn::Map(vec![MapMode::Insert, MapMode::Command], true, self.args())
+            }
+            "unmap" | "unm" | "nunmap" | "nun" => {
+                CommandReturn::Unmap(vec![MapMode::Normal], self.args())
+            }
+            "iabbrev" | "iab" | "ia" | "abbreviate" | "ab" => CommandReturn::Abbrev(self.args()),
+            "iunabbrev" | "iuna" | "unabbreviate" | "una" => CommandReturn::Unabbrev(self.args()),
+            "iabclear" | "iabc" | "abclear" | "abc" => CommandReturn::AbClear,
+            "colorscheme" | "colo" => CommandReturn::ColorScheme(self.args()),
+            "recover" | "rec" => CommandReturn::Recover,
+            "checktime" | "checkt" => CommandReturn::CheckTime,
+            "ls" | "files" => CommandReturn::BuffList,
+            "edit" | "e" => CommandReturn::Edit(self.value.clone()),
+            "edit!" | "e!" => CommandReturn::ForceEdit(self.value.clone()),
+            "enew" | "ene" => CommandReturn::Enew,
+            "saveas" | "sav" => CommandReturn::SaveAs(self.value.clone()),
+            "saveas!" | "sav!" => CommandReturn::ForceSaveAs(self.value.clone()),
+            "read" | "r" => CommandReturn::Read(self.value.clone()),
+            "file" | "f" => CommandReturn::File(self.value.clone()),
+            "args" | "ar" => CommandReturn::Args(self.args()),
+            "next" | "n" => CommandReturn::ArgNext,
+            "Next" | "N" | "previous" | "prev" => CommandReturn::ArgPrev,
+            "first" | "fir" | "rewind" | "rew" => CommandReturn::ArgFirst,
+            "last" | "la" => CommandReturn::ArgLast,
+            "argadd" | "arga" => CommandReturn::ArgAdd(self.args()),
+            "argdo" => CommandReturn::ArgDo(self.args()),
+            "update" | "up" => CommandReturn::Update,
+            "split" | "sp" => CommandReturn::Split(false, self.value.clone()),
+            "vsplit" | "vs" => CommandReturn::Split(true, self.value.clone()),
+            "close" | "clo" => CommandReturn::Close,
+            "only" | "on" => CommandReturn::Only,
+            "tabnew" | "tabedit" | "tabe" => CommandReturn::TabNew(self.value.clone()),
+            "tabclose" | "tabc" => CommandReturn::TabClose,
+            "tabnext" | "tabn" => CommandReturn::TabNext(self.count()),
+            "tabprevious" | "tabp" | "tabNext" | "tabN" => CommandReturn::TabPrev(self.count()),
+            "tabmove" | "tabm" => CommandReturn::TabMove(self.value.clone()),
+            "iunmap" | "iu" => CommandReturn::Unmap(vec![MapMode::Insert], self.args()),
+            "cunmap" | "cu" => CommandReturn::Unmap(vec![MapMode::Command], self.args()),
+            "unmap!" | "unm!" => {
+                CommandReturn::Unmap(vec![MapMode::Insert, MapMode::Command], self.args())
             }
+            _ => self.substitute().unwrap_or_else(|| {
+                CommandReturn::Error(format!("Not an editor command: {}", self.command_string))
+            }),
         }
     }
 }
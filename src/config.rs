@@ -0,0 +1,39 @@
+use std::{env, fs, io, path::PathBuf};
+
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("rusty-vim"))
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    let state_dir = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state_dir.join("rusty-vim"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("init.rv"))
+}
+
+// a leading `~` stands for the home directory
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => home + rest,
+        _ => path.to_string(),
+    }
+}
+
+pub fn read_commands(path: &str) -> io::Result<Vec<(usize, String)>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim().to_string()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('"'))
+        .collect())
+}
@@ -1,14 +1,32 @@
-use crate::buffer::TextBuffer;
+use crate::abbrev::Abbreviations;
+use crate::args::{Args, ConfigFile};
+use crate::autocmd::{AutoCmds, Event};
+use crate::backend::Backend;
+use crate::backend::headless::HeadlessBackend;
+use crate::backend::termios::TermiosBackend;
+use crate::buffer::{CharClass, DiskState, TextBuffer};
 use crate::commandmode::{CommandMode, CommandReturn};
-use crate::error::{AppError, Result};
+use crate::config;
+use crate::error::{AppError, FileError, Result};
 use crate::insertmode::InsertAction;
 use crate::insertmode::InsertType;
+use crate::keymap::{self, KeyMaps, MapLookup, MapMode};
 use crate::normalmode::NormalMode;
 use crate::normalmode::motions::BufferAction;
 use crate::normalmode::motions::Motion;
-use crate::terminal::Terminal;
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use crate::options::{self, OptionDef, OptionKind, OptionScope, OptionValue, Options, SetAction};
+use crate::prompt::{Prompt, PromptAction};
+use crate::swap::{self, SwapFile, SwapInfo};
+use crate::terminal::{Position, Terminal, View};
+use crate::theme::{ColorDepth, Theme};
+use crate::window::{Direction, Layout, Rect};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// how deep `:source` may nest, like Vim
+const MAX_SOURCE_DEPTH: usize = 15;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum EditorModes {
@@ -25,20 +43,102 @@ pub struct Editor {
     mode: EditorModes,
     normal_mode: NormalMode,
     command_mode: CommandMode,
+    options: Options,
+    autocmds: AutoCmds,
+    in_autocmd: bool,
+    source_depth: usize,
+    config_path: Option<String>,
+    keymaps: KeyMaps,
+    typeahead: VecDeque<(u8, bool)>,
+    last_key_time: Instant,
+    abbreviations: Abbreviations,
+    insert_literal: bool,
+    headless: Option<HeadlessBackend>,
+    prompts: VecDeque<Prompt>,
+    // keys typed since the swap files were last written
+    keys_since_sync: usize,
+    // `-r`: take the changes from an existing swap file without asking
+    recover: bool,
+    // when the files on disk were last compared with their buffers
+    last_checktime: Instant,
+    // key of the buffer that was current before this one, for ctrl-^ and `:b#`
+    alternate: Option<usize>,
+    // keys are never reused, so buffer numbers stay the same while the editor runs
+    next_key: usize,
+    // file names of buffers closed with `:bd` by key, opening one again gives it back its
+    // number, `:bw` forgets them
+    unlisted: HashMap<usize, String>,
+    // the files named when starting, or set by `:args`, apart from the buffer list
+    arglist: Vec<String>,
+    arg_index: usize,
+    // the windows of the current tab page
+    windows: Layout,
+    // the other tab pages, the current one goes in at index tab
+    tabs: Vec<Layout>,
+    tab: usize,
 }
 
 impl Editor {
     pub fn new(args: Vec<String>) -> Result<Self> {
+        let args = Args::parse(args)?;
+        let script = args.script.map(fs::read).transpose()?;
+        let headless = args.headless.then(|| HeadlessBackend::new(80, 24));
+        let backend: Box<dyn Backend> = match &headless {
+            Some(headless) => Box::new(headless.clone()),
+            None => Box::new(TermiosBackend::new()?),
+        };
+        let config_path = match args.config {
+            ConfigFile::Default => config::default_path()
+                .filter(|path| path.is_file())
+                .map(|path| path.to_string_lossy().into_owned()),
+            ConfigFile::Path(path) => Some(path),
+            ConfigFile::None => None,
+        };
+        let mut editor = Self::build(
+            args.files,
+            config_path,
+            backend,
+            args.recover,
+            args.readonly,
+        )?;
+        editor.headless = headless;
+        if let Some(keys) = script {
+            match &editor.headless {
+                Some(headless) => headless.push_keys(&keys),
+                None => editor.typeahead.extend(keys.into_iter().map(|c| (c, true))),
+            }
+        }
+        Ok(editor)
+    }
+
+    /// Creates an editor for `files` that draws to `backend` and reads no config file.
+    pub fn with_backend(files: Vec<String>, backend: Box<dyn Backend>) -> Result<Self> {
+        Self::build(files, None, backend, false, false)
+    }
+
+    fn build(
+        files: Vec<String>,
+        config_path: Option<String>,
+        backend: Box<dyn Backend>,
+        recover: bool,
+        readonly: bool,
+    ) -> Result<Self> {
+        let mut options = Options::new(OptionScope::Global);
+        if let Some(def) = options::find("termguicolors") {
+            let truecolor = ColorDepth::detect() == ColorDepth::TrueColor;
+            options.set(def, OptionValue::Bool(truecolor));
+        }
+        if readonly && let Some(def) = options::find("readonly") {
+            options.set(def, OptionValue::Bool(true));
+        }
+        // an empty buffer while the config runs, the files are opened once its options are set
         let mut buff_vec: Vec<usize> = Vec::new();
-        let buffers = TextBuffer::load_buffers(args, &mut buff_vec)?;
+        let buffers = TextBuffer::load_buffers(Vec::new(), &mut buff_vec, &options)?;
         let current_buff_index: usize = 0;
-        let curr_buff = buffers.get(&buff_vec[0]).unwrap();
-        let filename = match &curr_buff.filename {
-            Some(name) => name,
-            None => &String::new(),
-        };
-        let terminal = Terminal::new(curr_buff.rows.len(), filename)?;
-        Ok(Self {
+        let filename = files.first().cloned().unwrap_or_default();
+        let terminal = Terminal::new(0, &filename, backend)?;
+        let windows = Layout::new(buff_vec[0], Self::window_area(terminal.size, false));
+        let mut editor = Self {
             normal_mode: NormalMode::new(),
             buff_vec,
             command_mode: CommandMode::new(),
@@ -46,12 +146,82 @@ impl Editor {
             terminal,
             buffers,
             mode: EditorModes::Normal,
-        })
+            options,
+            autocmds: AutoCmds::default(),
+            in_autocmd: false,
+            source_depth: 0,
+            config_path,
+            keymaps: KeyMaps::default(),
+            typeahead: VecDeque::new(),
+            last_key_time: Instant::now(),
+            abbreviations: Abbreviations::default(),
+            insert_literal: false,
+            headless: None,
+            prompts: VecDeque::new(),
+            keys_since_sync: 0,
+            recover,
+            last_checktime: Instant::now(),
+            alternate: None,
+            next_key: 1001,
+            unlisted: HashMap::new(),
+            arglist: Vec::new(),
+            arg_index: 0,
+            windows,
+            tabs: Vec::new(),
+            tab: 0,
+        };
+        editor.startup(files)?;
+        Ok(editor)
+    }
+
+    fn startup(&mut self, files: Vec<String>) -> Result<()> {
+        if let Some(path) = self.config_path.clone()
+            && let Err(e) = self.source_file(&path)
+        {
+            self.command_mode.escape(&e);
+        }
+        self.buff_vec.clear();
+        self.arglist = files.clone();
+        self.buffers = TextBuffer::load_buffers(files, &mut self.buff_vec, &self.options)?;
+        self.next_key = self.buff_vec.iter().max().map_or(1000, |key| key + 1);
+        self.windows = Layout::new(
+            self.buff_vec[0],
+            Self::window_area(self.terminal.size, false),
+        );
+        let message = self.command_mode.command_string.clone();
+        for index in 0..self.buff_vec.len() {
+            self.current_buff_index = index;
+            let key = self.get_buff_key();
+            if !self.report_open_error(key) {
+                self.attach_swap(key);
+            }
+            let buffer = self.buffers.get_mut(&key).unwrap();
+            buffer.detect_filetype();
+            let event = match &buffer.filename {
+                Some(name) if Path::new(name).exists() => Event::BufRead,
+                Some(_) => Event::BufNewFile,
+                None => continue,
+            };
+            self.fire_autocmds(event);
+        }
+        self.current_buff_index = 0;
+        self.fire_autocmds(Event::BufEnter);
+        if self.command_mode.command_string.is_empty() {
+            self.command_mode.escape(&message);
+        }
+        self.terminal.command_line = self.command_mode.command_string.clone();
+        self.show_prompt();
+        Ok(())
     }
 
     pub fn run(&mut self) -> Result<()> {
+        self.process_typeahead();
+        self.render_ui()?;
         loop {
-            self.process_keypress()?;
+            match self.process_keypress() {
+                Err(AppError::EndOfInput) => return Ok(()),
+                result => result?,
+            }
             if self.buff_vec.is_empty() {
                 return Ok(());
             }
@@ -59,6 +229,82 @@ impl Editor {
         }
     }
 
+    /// The last frame drawn when started with `--headless`.
+    pub fn headless_screen(&self) -> Option<Vec<String>> {
+        self.headless.as_ref().map(|headless| headless.lines())
+    }
+
+    /// Handles `keys` as if they were typed, then redraws the screen.
+    pub fn feed_keys(&mut self, keys: &[u8]) -> Result<()> {
+        for &c in keys {
+            if !self.is_running() {
+                return Ok(());
+            }
+            self.handle_key(c);
+        }
+        if self.is_running() {
+            self.render_ui()?;
+        }
+        Ok(())
+    }
+
+    /// Runs an Ex command line such as `"set ts=4"` or `":w out.txt"`.
+    pub fn execute(&mut self, command: &str) -> std::result::Result<(), String> {
+        let command = self.command_mode.parse(command.trim_start_matches(':'));
+        let result = self.run_command(command);
+        if let Err(e) = &result {
+            self.command_mode.escape(e);
+        }
+        self.terminal.command_line = self.command_mode.command_string.clone();
+        if self.is_running() {
+            self.render_ui().map_err(|e| e.to_string())?;
+        }
+        result
+    }
+
+    /// Opens `filename` in a new buffer and makes it the current one.
+    pub fn open(&mut self, filename: &str) -> Result<()> {
+        let mut buffer = TextBuffer::new(Some(filename.to_string()), &self.options)?;
+        buffer.detect_filetype();
+        let event = if Path::new(filename).exists() {
+            Event::BufRead
+        } else {
+            Event::BufNewFile
+        };
+        let key = self.add_buffer(buffer);
+        if !self.report_open_error(key) {
+            self.attach_swap(key);
+        }
+        self.show_prompt();
+        self.fire_autocmds(event);
+        self.fire_autocmds(Event::BufEnter);
+        Ok(())
+    }
+
+    /// False once every buffer has been closed.
+    pub fn is_running(&self) -> bool {
+        !self.buff_vec.is_empty()
+    }
+
+    pub fn mode(&self) -> EditorModes {
+        self.mode
+    }
+
+    /// The text shown on the command line, such as the last message or error.
+    pub fn message(&self) -> &str {
+        &self.terminal.command_line
+    }
+
+    pub fn buffer(&self) -> Option<&TextBuffer> {
+        let key = self.buff_vec.get(self.current_buff_index)?;
+        self.buffers.get(key)
+    }
+
+    pub fn buffer_mut(&mut self) -> Option<&mut TextBuffer> {
+        let key = self.buff_vec.get(self.current_buff_index)?;
+        self.buffers.get_mut(key)
+    }
+
     fn get_buff_key(&mut self) -> usize {
         if self.buff_vec.len() <= self.current_buff_index {
             self.current_buff_index = self.buff_vec.len().saturating_sub(1);
@@ -67,12 +313,334 @@ impl Editor {
     }
 
     fn render_ui(&mut self) -> Result<()> {
-        let curr_buff_key = self.get_buff_key();
-        if let Some(buffer) = self.buffers.get(&curr_buff_key) {
-            self.terminal.refresh_screen(buffer, self.mode)?;
+        for buffer in self.buffers.values_mut() {
+            buffer.poll_loader();
+        }
+        // a background load can still find that a file is not UTF-8
+        let failed: Vec<usize> = self
+            .buffers
+            .iter()
+            .filter(|(_, buffer)| buffer.open_error.is_some())
+            .map(|(key, _)| *key)
+            .collect();
+        for key in failed {
+            self.report_open_error(key);
+            self.show_prompt();
+        }
+        if self.buff_vec.is_empty() {
+            return Err(AppError::BufferError(String::from("invalid buffer")));
+        }
+        self.fit_windows();
+        self.prune_windows();
+        self.terminal.tabline = self.tab_labels();
+        if self.options.get_bool("syntax") {
+            for (id, rect) in self.windows.rects() {
+                let window = self.windows.window(id);
+                if let Some(buffer) = self.buffers.get_mut(&window.buffer) {
+                    let upto = window.camera.y.max(window.pos.y) + rect.height;
+                    buffer.syntax.update(&buffer.rows, upto);
+                }
+            }
+        }
+        let mut views = Vec::new();
+        for (rect, current, window) in self.windows.visible() {
+            let Some(buffer) = self.buffers.get(&window.buffer) else {
+                continue;
+            };
+            let pos = if current {
+                buffer.pos
+            } else {
+                buffer.clamp(window.pos)
+            };
+            views.push(View {
+                buffer,
+                pos,
+                camera: &mut window.camera,
+                rect,
+                current,
+            });
+        }
+        self.terminal
+            .refresh_screen(&mut views, self.mode, &self.options)
+    }
+
+    // the screen above the command line and below the tabline
+    fn window_area(size: Position, tabline: bool) -> Rect {
+        let top = usize::from(tabline);
+        Rect {
+            x: 0,
+            y: top,
+            width: size.x,
+            height: size.y.saturating_sub(1 + top),
+        }
+    }
+
+    fn fit_windows(&mut self) {
+        let area = Self::window_area(self.terminal.size, !self.tabs.is_empty());
+        self.windows.set_area(area);
+    }
+
+    // the window count, `+` when a buffer in it has changes and the current file of each tab
+    fn tab_labels(&self) -> Vec<(String, bool)> {
+        if self.tabs.is_empty() {
+            return Vec::new();
+        }
+        let (before, after) = self.tabs.split_at(self.tab);
+        let tabs = before
+            .iter()
+            .chain(std::iter::once(&self.windows))
+            .chain(after);
+        tabs.enumerate()
+            .map(|(index, layout)| {
+                let name = self
+                    .buffers
+                    .get(&layout.current().buffer)
+                    .and_then(|buffer| buffer.filename.as_deref())
+                    .map_or("[No Name]", |name| {
+                        Path::new(name)
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or(name)
+                    });
+                let mut prefix = String::new();
+                if layout.len() > 1 {
+                    prefix.push_str(&layout.len().to_string());
+                }
+                if layout.buffers().any(|key| {
+                    self.buffers
+                        .get(&key)
+                        .is_some_and(|buffer| buffer.is_changed)
+                }) {
+                    prefix.push('+');
+                }
+                let label = match prefix.is_empty() {
+                    true => name.to_string(),
+                    false => format!("{} {}", prefix, name),
+                };
+                (label, index == self.tab)
+            })
+            .collect()
+    }
+
+    // whether a window besides the current one shows buffer, in any tab page
+    fn shown_elsewhere(&self, buffer: usize) -> bool {
+        self.windows.shows_elsewhere(buffer) || self.tabs.iter().any(|tab| tab.shows(buffer))
+    }
+
+    // `:tabnew`: a tab page after the current one, with filename or a new empty buffer
+    fn new_tab(&mut self, filename: Option<String>) -> std::result::Result<(), String> {
+        self.save_window();
+        let key = match filename {
+            Some(_) => self.get_buff_key(),
+            None => {
+                let buffer = TextBuffer::new(None, &self.options).map_err(|e| e.to_string())?;
+                self.add_buffer(buffer)
+            }
+        };
+        let area = Self::window_area(self.terminal.size, true);
+        let layout = std::mem::replace(&mut self.windows, Layout::new(key, area));
+        self.tabs.insert(self.tab, layout);
+        self.tab += 1;
+        match filename {
+            Some(name) => self.show_file(&name)?,
+            None => self.fire_autocmds(Event::BufEnter),
+        }
+        Ok(())
+    }
+
+    // the tab page after it becomes current, or the one before when it was last
+    fn close_tab(&mut self) -> std::result::Result<(), String> {
+        if self.tabs.is_empty() {
+            return Err(String::from("Cannot close last tab page"));
+        }
+        self.save_window();
+        if self.tab == self.tabs.len() {
+            self.tab -= 1;
+        }
+        self.windows = self.tabs.remove(self.tab);
+        self.fit_windows();
+        self.load_window();
+        Ok(())
+    }
+
+    fn go_to_tab(&mut self, index: usize) {
+        if index == self.tab || index > self.tabs.len() {
+            return;
+        }
+        self.save_window();
+        let layout = self
+            .tabs
+            .remove(if index < self.tab { index } else { index - 1 });
+        let layout = std::mem::replace(&mut self.windows, layout);
+        let at = if index < self.tab {
+            self.tab - 1
+        } else {
+            self.tab
+        };
+        self.tabs.insert(at, layout);
+        self.tab = index;
+        self.fit_windows();
+        self.load_window();
+    }
+
+    // gt goes to the next tab page or to page count, gT goes count pages back
+    fn tab_command(&mut self, c: char, count: usize) {
+        let pages = self.tabs.len() + 1;
+        let index = match (c, count) {
+            ('t', 0) => (self.tab + 1) % pages,
+            ('t', count) => count.min(pages) - 1,
+            (_, count) => (self.tab + pages - count.max(1) % pages) % pages,
+        };
+        self.go_to_tab(index);
+    }
+
+    // `:tabmove`: to the end, after page N (0 for first), or +N and -N pages from where it is
+    fn move_tab(&mut self, to: Option<String>) -> std::result::Result<(), String> {
+        let last = self.tabs.len();
+        let invalid = || format!("Invalid argument: {}", to.as_deref().unwrap_or_default());
+        let index = match to.as_deref() {
+            None => last,
+            Some(to) if to.starts_with(['+', '-']) => {
+                let offset: isize = to.parse().map_err(|_| invalid())?;
+                self.tab.saturating_add_signed(offset).min(last)
+            }
+            Some(to) => to.parse::<usize>().map_err(|_| invalid())?.min(last),
+        };
+        self.tab = index;
+        Ok(())
+    }
+
+    // the current window shows the current buffer, its cursor stays in the buffer until
+    // another window is entered
+    fn save_window(&mut self) {
+        if self.buff_vec.is_empty() {
+            return;
+        }
+        let key = self.get_buff_key();
+        let pos = self.buffers[&key].pos;
+        let window = self.windows.current_mut();
+        window.buffer = key;
+        window.pos = pos;
+    }
+
+    fn load_window(&mut self) {
+        let window = self.windows.current();
+        let (key, pos) = (window.buffer, window.pos);
+        let Some(index) = self.buff_vec.iter().position(|other| *other == key) else {
+            return;
+        };
+        let entered = self.buff_vec.get(self.current_buff_index) != Some(&key);
+        self.current_buff_index = index;
+        let buffer = self.buffers.get_mut(&key).unwrap();
+        buffer.pos = buffer.clamp(pos);
+        if entered {
+            self.fire_autocmds(Event::BufEnter);
+        }
+    }
+
+    fn enter_window(&mut self, id: usize) {
+        if id == self.windows.current_id() {
+            return;
+        }
+        self.save_window();
+        self.windows.set_current(id);
+        self.load_window();
+    }
+
+    // windows of closed buffers go too, unless no other window would be left
+    fn prune_windows(&mut self) {
+        self.save_window();
+        let gone: Vec<usize> = self
+            .windows
+            .ids()
+            .into_iter()
+            .filter(|id| !self.buffers.contains_key(&self.windows.window(*id).buffer))
+            .collect();
+        for id in gone {
+            let _ = self.windows.close(id);
+        }
+    }
+
+    // `:q` closes the window, or the tab page with its last window, and the buffer unless
+    // another window shows it
+    fn quit_window(&mut self) {
+        if self.windows.len() < 2 && self.tabs.is_empty() {
+            self.close_current_buffer();
+            return;
+        }
+        let key = self.get_buff_key();
+        let shown = self.shown_elsewhere(key);
+        let _ = match self.windows.len() {
+            1 => self.close_tab(),
+            _ => self.close_window(),
+        };
+        if !shown {
+            if self.alternate == Some(key) {
+                self.alternate = None;
+            }
+            self.buffers.remove(&key);
+            self.buff_vec.retain(|other| *other != key);
+            // the buffer indexes moved
+            self.load_window();
+        }
+    }
+
+    // `:close`, the buffer stays in the buffer list
+    fn close_window(&mut self) -> std::result::Result<(), String> {
+        self.save_window();
+        self.windows.close(self.windows.current_id())?;
+        self.load_window();
+        Ok(())
+    }
+
+    fn split_window(
+        &mut self,
+        vertical: bool,
+        filename: Option<String>,
+    ) -> std::result::Result<(), String> {
+        self.save_window();
+        self.windows.split(vertical)?;
+        if let Some(name) = filename {
+            self.show_file(&name)?;
+        }
+        Ok(())
+    }
+
+    // ctrl-w followed by c, count is 0 without one
+    fn window_command(&mut self, c: char, count: usize) -> std::result::Result<(), String> {
+        let direction = match c {
+            'h' => Some(Direction::Left),
+            'j' => Some(Direction::Down),
+            'k' => Some(Direction::Up),
+            'l' => Some(Direction::Right),
+            _ => None,
+        };
+        if let Some(direction) = direction {
+            let id = self
+                .windows
+                .neighbour(direction, self.terminal.cursor, count);
+            self.enter_window(id);
             return Ok(());
         }
-        Err(AppError::BufferError(String::from("invalid buffer")))
+        let amount = count.max(1) as isize;
+        match c {
+            'w' => self.enter_window(self.windows.next((count != 0).then_some(count))),
+            'c' => self.close_window()?,
+            'q' => self.run_command(CommandReturn::Quit)?,
+            'o' => {
+                self.save_window();
+                self.windows.only();
+            }
+            's' => self.split_window(false, None)?,
+            'v' => self.split_window(true, None)?,
+            '=' => self.windows.equalize(),
+            '+' => self.windows.resize(false, amount),
+            '-' => self.windows.resize(false, -amount),
+            '>' => self.windows.resize(true, amount),
+            '<' => self.windows.resize(true, -amount),
+            _ => (),
+        }
+        Ok(())
     }
 
     fn process_normal_mode(&mut self, c: u8) {
@@ -91,7 +659,38 @@ impl Editor {
             let curr_buff_key = self.get_buff_key();
             if let Some(buffer) = self.buffers.get_mut(&curr_buff_key) {
                 match action {
+                    BufferAction::Delete(_) | BufferAction::ChangeMode(EditorModes::Insert, _)
+                        if !buffer.is_modifiable() =>
+                    {
+                        self.command_mode
+                            .escape("Cannot make changes, 'modifiable' is off");
+                        self.terminal.command_line = self.command_mode.command_string.clone();
+                    }
                     BufferAction::Delete(direction) => buffer.delete(direction),
+                    BufferAction::Command(line) => {
+                        let command = self.command_mode.parse(line);
+                        if let Err(e) = self.run_command(command) {
+                            self.command_mode.escape(&e);
+                        }
+                        self.terminal.command_line = self.command_mode.command_string.clone();
+                    }
+                    BufferAction::Window(c, count) => {
+                        if let Err(e) = self.window_command(c, count) {
+                            self.command_mode.escape(&e);
+                            self.terminal.command_line = self.command_mode.command_string.clone();
+                        }
+                    }
+                    BufferAction::Tab(c, count) => self.tab_command(c, count),
+                    BufferAction::SwitchBuffer(n) => {
+                        let result = match n {
+                            Some(n) => self.buf_n(n),
+                            None => self.buf_alternate(),
+                        };
+                        if let Err(e) = result {
+                            self.command_mode.escape(&e);
+                            self.terminal.command_line = self.command_mode.command_string.clone();
+                        }
+                    }
                     BufferAction::Move(direction) => buffer.motion(direction),
                     BufferAction::ChangeMode(mode, pos) => self.change_mode(mode, pos),
                     BufferAction::None => (),
@@ -119,127 +718,1274 @@ impl Editor {
     fn process_insert_mode(&mut self, c: u8) {
         let curr_buff_key = self.get_buff_key();
         if let Some(buffer) = self.buffers.get_mut(&curr_buff_key) {
-            match InsertAction::handle_key(c) {
+            if self.insert_literal {
+                self.insert_literal = false;
+                match c {
+                    9 => buffer.insert_text("\t"),
+                    c if c >= 32 => buffer.insert_text(&(c as char).to_string()),
+                    _ => (),
+                }
+                return;
+            }
+            let action = InsertAction::handle_key(c);
+            // a byte of a multibyte character is taken as part of a word, like letters are
+            if let InsertAction::Chars(_) | InsertAction::Newline | InsertAction::Escape = action
+                && c.is_ascii()
+                && TextBuffer::find_char_class(c as char) != CharClass::Keyword
+                && let Some(word) = buffer.word_before_cursor()
+                && let Some(expansion) = self.abbreviations.get(word)
+            {
+                buffer.replace_before_cursor(word.len(), expansion);
+            }
+            match action {
                 InsertAction::Backspace => buffer.delete(Motion::BackSpace(1)),
                 InsertAction::Escape => self.change_mode(EditorModes::Normal, InsertType::None),
                 InsertAction::Newline => buffer.split_line(),
                 InsertAction::Chars(c) => buffer.insert_char(c),
+                InsertAction::Literal => self.insert_literal = true,
                 InsertAction::None => (),
             }
         }
     }
 
     fn process_command_mode(&mut self, c: u8) {
-        let mut should_quit = false;
+        let command = self.command_mode.handle_key(c);
+        if let Err(e) = self.run_command(command) {
+            self.command_mode.escape(&e);
+        }
+        if self.mode == EditorModes::Command {
+            self.terminal.command_line = String::from(":");
+        } else {
+            self.terminal.command_line = String::new();
+        }
+        self.terminal
+            .command_line
+            .push_str(&self.command_mode.command_string);
+    }
+
+    fn run_command(&mut self, command: CommandReturn) -> std::result::Result<(), String> {
+        if let CommandReturn::None = command {
+            return Ok(());
+        }
+        self.mode = EditorModes::Normal;
+        self.command_mode.escape("");
         let curr_buff_key = self.get_buff_key();
-        if let Some(buffer) = self.buffers.get_mut(&curr_buff_key) {
-            match self.command_mode.handle_key(c) {
-                CommandReturn::Quit => {
-                    if !buffer.is_changed {
-                        self.command_mode.escape("");
-                        should_quit = true;
-                    } else {
-                        self.command_mode
-                            .escape("file  changes use q! to force quit");
-                        self.change_mode(EditorModes::Normal, InsertType::None);
+        let buffer = self
+            .buffers
+            .get_mut(&curr_buff_key)
+            .ok_or(String::from("invalid buffer"))?;
+        match command {
+            CommandReturn::Quit => {
+                if buffer.is_changed && !self.shown_elsewhere(curr_buff_key) {
+                    self.change_mode(EditorModes::Normal, InsertType::None);
+                    return Err(String::from("file  changes use q! to force quit"));
+                }
+                self.quit_window();
+            }
+            CommandReturn::ForceQuit => self.quit_window(),
+            CommandReturn::Exit(filename) => {
+                if buffer.is_changed || filename.is_some() {
+                    if buffer.is_readonly() && (filename.is_none() || filename == buffer.filename) {
+                        return Err(String::from("'readonly' option is set (add ! to override)"));
+                    }
+                    let result = buffer.write_buffer_file(false, filename, &self.options);
+                    self.handle_write_result(result)?;
+                }
+                self.quit_window();
+            }
+            CommandReturn::QuitAll => {
+                self.check_unsaved()?;
+                self.quit_all();
+            }
+            CommandReturn::ForceQuitAll => self.quit_all(),
+            CommandReturn::SaveAll => self.write_all()?,
+            CommandReturn::SaveQuitAll => {
+                self.write_all()?;
+                self.check_unsaved()?;
+                self.quit_all();
+            }
+            CommandReturn::Save(ref name) | CommandReturn::SaveQuit(ref name)
+                if buffer.is_readonly()
+                    && (name.is_none() || name.as_ref() == buffer.filename.as_ref()) =>
+            {
+                return Err(String::from("'readonly' option is set (add ! to override)"));
+            }
+            CommandReturn::Save(filename) | CommandReturn::ForceSave(filename) => {
+                let result = buffer.write_buffer_file(true, filename, &self.options);
+                self.handle_write_result(result)?;
+            }
+            CommandReturn::ForceSaveQuit(filename) => {
+                let result = buffer.write_buffer_file(true, filename, &self.options);
+                self.handle_write_result(result)?;
+                self.quit_window();
+            }
+            CommandReturn::SaveQuit(filename) => {
+                let result = buffer.write_buffer_file(false, filename, &self.options);
+                self.handle_write_result(result)?;
+                self.quit_window();
+            }
+            CommandReturn::BuffNext => self.buf_next(),
+            CommandReturn::BuffPrev => self.buf_prev(),
+            CommandReturn::BuffN(n) => self.buf_n(n)?,
+            CommandReturn::BuffAlternate => self.buf_alternate()?,
+            CommandReturn::BuffName(name) => {
+                let index = self.find_buffer(&name)?;
+                self.switch_buffer(index);
+            }
+            CommandReturn::BuffList => {
+                let lines = self.list_buffers();
+                match lines.len() {
+                    1 => self.command_mode.escape(&lines[0]),
+                    _ => self.terminal.messages = lines,
+                }
+            }
+            CommandReturn::BuffDelete(name, force, wipe) => {
+                // a buffer closed with `:bd` can still be wiped by its number
+                if wipe
+                    && let Some(n) = name.as_ref().and_then(|name| name.parse::<usize>().ok())
+                    && self.unlisted.remove(&Self::buffer_key(n)).is_some()
+                {
+                    if self.alternate == Some(Self::buffer_key(n)) {
+                        self.alternate = None;
                     }
-                    self.mode = EditorModes::Normal;
+                    return Ok(());
                 }
-                CommandReturn::ForceQuit => {
-                    self.mode = EditorModes::Normal;
-                    self.command_mode.escape("");
-                    should_quit = true;
+                let index = match name {
+                    Some(name) => self.find_buffer(&name)?,
+                    None => self.current_buff_index,
+                };
+                self.delete_buffer(index, force, wipe)?;
+            }
+            CommandReturn::Set(args) => self.set_options(&args, false)?,
+            CommandReturn::SetLocal(args) => self.set_options(&args, true)?,
+            CommandReturn::Source(path) => {
+                let path = path
+                    .or(self.config_path.clone())
+                    .ok_or(String::from("Argument required"))?;
+                self.source_file(&path)?;
+            }
+            CommandReturn::AutoCmd(args) if args.is_empty() => {
+                self.terminal.messages = self.autocmds.list();
+            }
+            CommandReturn::AutoCmd(args) => self.autocmds.add(&args)?,
+            CommandReturn::AutoCmdClear(args) => self.autocmds.clear(&args)?,
+            CommandReturn::Map(modes, noremap, args) => self.map_command(&modes, noremap, &args),
+            CommandReturn::Unmap(modes, args) => {
+                let lhs = keymap::parse_keys(&args, &self.leader());
+                self.keymaps.remove(&modes, &lhs)?;
+            }
+            CommandReturn::Abbrev(args) => match args.split_once(char::is_whitespace) {
+                Some((lhs, rhs)) => self.abbreviations.add(lhs, rhs.trim()),
+                None => {
+                    let lines = self.abbreviations.list(&args);
+                    match lines.len() {
+                        0 => self.command_mode.escape("No abbreviation found"),
+                        1 => self.command_mode.escape(&lines[0]),
+                        _ => self.terminal.messages = lines,
+                    }
                 }
-                CommandReturn::ForceSave(filename) => {
-                    let result = buffer.write_buffer_file(true, filename);
-                    self.command_mode.handle_file_write_result(result);
-                    self.mode = EditorModes::Normal;
+            },
+            CommandReturn::Unabbrev(args) => self.abbreviations.remove(&args)?,
+            CommandReturn::AbClear => self.abbreviations.clear(),
+            CommandReturn::ColorScheme(name) if name.is_empty() => {
+                self.command_mode
+                    .escape(&self.terminal.theme().name.clone());
+            }
+            CommandReturn::ColorScheme(name) => self.terminal.set_theme(Theme::load(&name)?),
+            CommandReturn::CheckTime => self.check_time(),
+            CommandReturn::Edit(name) => self.edit(name, false)?,
+            CommandReturn::ForceEdit(name) => self.edit(name, true)?,
+            CommandReturn::Enew => {
+                let buffer = TextBuffer::new(None, &self.options).map_err(|e| e.to_string())?;
+                self.add_buffer(buffer);
+                self.fire_autocmds(Event::BufEnter);
+            }
+            CommandReturn::SaveAs(name) => self.save_as(name, false)?,
+            CommandReturn::ForceSaveAs(name) => self.save_as(name, true)?,
+            CommandReturn::Read(name) => {
+                let name = name
+                    .or(buffer.filename.clone())
+                    .ok_or(String::from("No file name"))?;
+                if !buffer.is_modifiable() {
+                    return Err(String::from("Cannot make changes, 'modifiable' is off"));
                 }
-                CommandReturn::None => (),
-                CommandReturn::Save(filename) => {
-                    let result = buffer.write_buffer_file(true, filename);
-                    self.command_mode.handle_file_write_result(result);
-                    self.mode = EditorModes::Normal;
+                let lines = buffer.read_below(&name).map_err(|e| e.to_string())?;
+                self.command_mode
+                    .escape(&format!("\"{}\" {} lines", name, lines));
+            }
+            CommandReturn::File(Some(name)) => {
+                buffer.set_filename(name);
+                buffer.detect_filetype();
+                buffer.swap = None;
+                self.attach_swap(curr_buff_key);
+                self.show_prompt();
+                let info = self.buffers[&curr_buff_key].file_info();
+                self.command_mode.escape(&info);
+            }
+            CommandReturn::File(None) => self.command_mode.escape(&buffer.file_info()),
+            CommandReturn::Recover => {
+                let name = buffer
+                    .filename
+                    .clone()
+                    .ok_or(String::from("No file name"))?;
+                let own = buffer.swap.as_ref().map(|swap| swap.path().to_path_buf());
+                let dir = self.swap_dir().ok_or(String::from("No swap directory"))?;
+                let swap = swap::find(&dir, &name, own.as_deref())
+                    .into_iter()
+                    .next()
+                    .ok_or(format!("No swap file found for {}", name))?;
+                self.recover_swap(curr_buff_key, swap)?;
+            }
+            CommandReturn::Args(names) if names.is_empty() => {
+                let list = self.list_args();
+                self.command_mode.escape(&list);
+            }
+            CommandReturn::Args(names) => {
+                self.arglist = names.split_whitespace().map(String::from).collect();
+                self.go_to_arg(0)?;
+            }
+            CommandReturn::ArgNext | CommandReturn::ArgPrev if self.arglist.len() < 2 => {
+                return Err(String::from("There is only one file to edit"));
+            }
+            CommandReturn::ArgNext => {
+                if self.arg_index + 1 >= self.arglist.len() {
+                    return Err(String::from("Cannot go beyond last file"));
+                }
+                self.go_to_arg(self.arg_index + 1)?;
+            }
+            CommandReturn::ArgPrev => {
+                if self.arg_index == 0 {
+                    return Err(String::from("Cannot go before first file"));
                 }
-                CommandReturn::ForceSaveQuit(filename) => {
-                    let result = buffer.write_buffer_file(true, filename);
-                    should_quit = result.is_ok();
-                    self.command_mode.handle_file_write_result(result);
-                    self.mode = EditorModes::Normal;
+                self.go_to_arg(self.arg_index - 1)?;
+            }
+            CommandReturn::ArgFirst => self.go_to_arg(0)?,
+            CommandReturn::ArgLast => self.go_to_arg(self.arglist.len().saturating_sub(1))?,
+            CommandReturn::ArgAdd(names) => {
+                let mut names: Vec<String> = names.split_whitespace().map(String::from).collect();
+                if names.is_empty() {
+                    names.push(
+                        buffer
+                            .filename
+                            .clone()
+                            .ok_or(String::from("No file name"))?,
+                    );
+                }
+                // added after the current file
+                let at = match self.arglist.is_empty() {
+                    true => 0,
+                    false => self.arg_index + 1,
+                };
+                self.arglist.splice(at..at, names);
+            }
+            CommandReturn::ArgDo(line) => self.run_in_each(&line, true)?,
+            CommandReturn::BufDo(line) => self.run_in_each(&line, false)?,
+            CommandReturn::Update => {
+                if buffer.is_changed {
+                    return self.run_command(CommandReturn::Save(None));
+                }
+            }
+            CommandReturn::Substitute(whole, pattern, replacement, global, quiet) => {
+                if !buffer.is_modifiable() {
+                    return Err(String::from("Cannot make changes, 'modifiable' is off"));
+                }
+                match buffer.substitute(whole, &pattern, &replacement, global) {
+                    (0, _) if !quiet => return Err(format!("Pattern not found: {}", pattern)),
+                    (0 | 1, _) => (),
+                    (count, lines) => self
+                        .command_mode
+                        .escape(&format!("{} substitutions on {} lines", count, lines)),
+                }
+            }
+            CommandReturn::Split(vertical, name) => self.split_window(vertical, name)?,
+            CommandReturn::Close => self.close_window()?,
+            CommandReturn::Only => {
+                self.save_window();
+                self.windows.only();
+            }
+            CommandReturn::TabNew(name) => self.new_tab(name)?,
+            CommandReturn::TabClose => self.close_tab()?,
+            CommandReturn::TabNext(count) => self.tab_command('t', count),
+            CommandReturn::TabPrev(count) => self.tab_command('T', count),
+            CommandReturn::TabMove(to) => self.move_tab(to)?,
+            CommandReturn::Bar(first, rest) => {
+                let command = self.command_mode.parse(&first);
+                self.run_command(command)?;
+                let message = self.command_mode.command_string.clone();
+                if self.is_running() {
+                    let command = self.command_mode.parse(&rest);
+                    self.run_command(command)?;
                 }
-                CommandReturn::SaveQuit(filename) => {
-                    let result = buffer.write_buffer_file(false, filename);
-                    should_quit = result.is_ok();
-                    self.mode = EditorModes::Normal;
-                    self.command_mode.handle_file_write_result(result);
+                if self.command_mode.command_string.is_empty() {
+                    self.command_mode.escape(&message);
                 }
-                CommandReturn::Escape => {
-                    self.command_mode.escape("");
-                    self.mode = EditorModes::Normal;
+            }
+            CommandReturn::Error(e) => return Err(e),
+            CommandReturn::Escape | CommandReturn::None => (),
+        }
+        Ok(())
+    }
+
+    fn handle_write_result(
+        &mut self,
+        result: std::result::Result<String, FileError>,
+    ) -> std::result::Result<(), String> {
+        let written = result.is_ok();
+        self.command_mode.handle_file_write_result(result)?;
+        if written {
+            let message = self.command_mode.command_string.clone();
+            self.fire_autocmds(Event::BufWritePost);
+            self.command_mode.escape(&message);
+        }
+        Ok(())
+    }
+
+    fn leader(&self) -> Vec<u8> {
+        keymap::parse_keys(&self.options.get_string("mapleader"), b"")
+    }
+
+    fn map_command(&mut self, modes: &[MapMode], noremap: bool, args: &str) {
+        let (lhs, rhs) = args
+            .split_once(char::is_whitespace)
+            .map_or((args, ""), |(lhs, rhs)| (lhs, rhs.trim()));
+        let leader = self.leader();
+        let lhs = keymap::parse_keys(lhs, &leader);
+        if rhs.is_empty() {
+            let lines = self.keymaps.list(modes, &lhs);
+            match lines.len() {
+                0 => self.command_mode.escape("No mapping found"),
+                1 => self.command_mode.escape(&lines[0]),
+                _ => self.terminal.messages = lines,
+            }
+            return;
+        }
+        let rhs = keymap::parse_keys(rhs, &leader);
+        self.keymaps.add(modes, lhs, rhs, noremap);
+    }
+
+    fn close_current_buffer(&mut self) {
+        let curr_buff_key = self.get_buff_key();
+        if self.alternate == Some(curr_buff_key) {
+            self.alternate = None;
+        }
+        self.buffers.remove(&curr_buff_key);
+        self.buff_vec.remove(self.current_buff_index);
+        self.current_buff_index = self.current_buff_index.saturating_sub(1);
+    }
+
+    // `:e`: switches to the buffer of filename or opens it in a new one, without a name the
+    // current file is read again, dropping changes only with force
+    fn edit(&mut self, filename: Option<String>, force: bool) -> std::result::Result<(), String> {
+        if let Some(name) = filename {
+            match self.buffer_for_file(&name)? {
+                Some(index) if index == self.current_buff_index => {
+                    return self.edit(None, force);
                 }
-                CommandReturn::BuffNext => self.buf_next(),
-                CommandReturn::BuffPrev => self.buf_prev(),
-                CommandReturn::BuffN(n) => self.buf_n(n),
+                Some(index) => self.switch_buffer(index),
+                None => self.open(&name).map_err(|e| e.to_string())?,
+            }
+            if self.command_mode.command_string.is_empty()
+                && let Some(buffer) = self.buffer()
+            {
+                let info = buffer.file_info();
+                self.command_mode.escape(&info);
             }
-            if self.mode == EditorModes::Command {
-                self.terminal.command_line = String::from(":");
+            return Ok(());
+        }
+        let key = self.get_buff_key();
+        let buffer = self.buffers.get_mut(&key).unwrap();
+        if buffer.filename.is_none() {
+            return Err(String::from("No file name"));
+        }
+        if buffer.is_changed && !force {
+            return Err(String::from(
+                "No write since last change (add ! to override)",
+            ));
+        }
+        buffer.reload(&self.options).map_err(|e| e.to_string())?;
+        let info = buffer.file_info();
+        self.fire_autocmds(Event::BufRead);
+        self.command_mode.escape(&info);
+        Ok(())
+    }
+
+    // the index of the buffer editing filename
+    fn buffer_for_file(&self, filename: &str) -> std::result::Result<Option<usize>, String> {
+        let path = std::path::absolute(filename).map_err(|e| e.to_string())?;
+        Ok(self.buff_vec.iter().position(|key| {
+            self.buffers[key]
+                .filename
+                .as_ref()
+                .and_then(|other| std::path::absolute(other).ok())
+                .is_some_and(|other| other == path)
+        }))
+    }
+
+    // makes the file at index of the argument list current, the buffers left keep their changes
+    fn go_to_arg(&mut self, index: usize) -> std::result::Result<(), String> {
+        let name = self
+            .arglist
+            .get(index)
+            .cloned()
+            .ok_or(String::from("Argument list is empty"))?;
+        self.arg_index = index;
+        self.show_file(&name)
+    }
+
+    // makes the buffer of filename current, opening it when there is none, unlike `:e` the
+    // current file is left as it is
+    fn show_file(&mut self, filename: &str) -> std::result::Result<(), String> {
+        match self.buffer_for_file(filename)? {
+            Some(index) if index == self.current_buff_index => (),
+            Some(index) => self.switch_buffer(index),
+            None => self.open(filename).map_err(|e| e.to_string())?,
+        }
+        if self.command_mode.command_string.is_empty()
+            && let Some(buffer) = self.buffer()
+        {
+            let info = buffer.file_info();
+            self.command_mode.escape(&info);
+        }
+        Ok(())
+    }
+
+    // `:args`: the argument list, the current file in brackets
+    fn list_args(&self) -> String {
+        self.arglist
+            .iter()
+            .enumerate()
+            .map(|(index, name)| match index == self.arg_index {
+                true => format!("[{}]", name),
+                false => name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // runs line in every file of the argument list, or every buffer, stopping at an error
+    fn run_in_each(&mut self, line: &str, args: bool) -> std::result::Result<(), String> {
+        if line.is_empty() {
+            return Err(String::from("Argument required"));
+        }
+        // indexes into the argument list, or buffer keys as the command may close buffers
+        let targets: Vec<usize> = match args {
+            true => (0..self.arglist.len()).collect(),
+            false => self.buff_vec.clone(),
+        };
+        for target in targets {
+            if args {
+                self.go_to_arg(target)?;
+            } else if let Some(index) = self.buff_vec.iter().position(|key| *key == target) {
+                self.switch_buffer(index);
             } else {
-                self.terminal.command_line = String::new();
+                continue;
+            }
+            let command = self.command_mode.parse(line);
+            self.run_command(command)?;
+            if !self.is_running() {
+                break;
             }
+        }
+        Ok(())
+    }
+
+    // writes the buffer to filename and makes that the buffer's file
+    fn save_as(
+        &mut self,
+        filename: Option<String>,
+        force: bool,
+    ) -> std::result::Result<(), String> {
+        let name = filename.ok_or(String::from("Argument required"))?;
+        if !force && Path::new(&name).exists() {
+            return Err(String::from("File exists (add ! to override)"));
+        }
+        let key = self.get_buff_key();
+        let buffer = self.buffers.get_mut(&key).unwrap();
+        let result = buffer.save_as(name, &self.options);
+        if result.is_err() {
+            return self.handle_write_result(result);
+        }
+        buffer.detect_filetype();
+        buffer.swap = None;
+        self.attach_swap(key);
+        self.show_prompt();
+        self.handle_write_result(result)
+    }
+
+    fn quit_all(&mut self) {
+        self.buffers.clear();
+        self.buff_vec.clear();
+        self.tabs.clear();
+        self.alternate = None;
+    }
+
+    // refuses to quit while a buffer has changes: lists them and goes to the first one
+    fn check_unsaved(&mut self) -> std::result::Result<(), String> {
+        let unsaved: Vec<usize> = (0..self.buff_vec.len())
+            .filter(|index| self.buffers[&self.buff_vec[*index]].is_changed)
+            .collect();
+        let Some(&first) = unsaved.first() else {
+            return Ok(());
+        };
+        let number = Self::buffer_number(self.buff_vec[first]);
+        self.switch_buffer(first);
+        if unsaved.len() > 1 {
+            let lines = self.list_buffers();
+            self.terminal.messages = vec![String::from("No write since last change for:")];
             self.terminal
-                .command_line
-                .push_str(&self.command_mode.command_string);
+                .messages
+                .extend(unsaved.iter().map(|index| lines[*index].clone()));
+        }
+        Err(format!(
+            "No write since last change for buffer {} (add ! to override)",
+            number
+        ))
+    }
+
+    // `:wa`: writes every buffer with changes, the ones that fail keep their changes
+    fn write_all(&mut self) -> std::result::Result<(), String> {
+        let current = self.current_buff_index;
+        let mut written = 0;
+        let mut errors = Vec::new();
+        for index in 0..self.buff_vec.len() {
+            let key = self.buff_vec[index];
+            let buffer = self.buffers.get_mut(&key).unwrap();
+            if !buffer.is_changed {
+                continue;
+            }
+            let number = Self::buffer_number(key);
+            if buffer.is_readonly() {
+                errors.push(format!("buffer {}: 'readonly' option is set", number));
+                continue;
+            }
+            let result = buffer.write_buffer_file(false, None, &self.options);
+            // the autocmds of a write run with its buffer current
+            self.current_buff_index = index;
+            match self.handle_write_result(result) {
+                Ok(()) => written += 1,
+                Err(e) => errors.push(format!("buffer {}: {}", number, e)),
+            }
+        }
+        self.current_buff_index = current;
+        self.command_mode.escape("");
+        match errors.len() {
+            0 => {
+                self.command_mode
+                    .escape(&format!("{} buffers written", written));
+                Ok(())
+            }
+            1 => Err(errors.remove(0)),
+            _ => {
+                self.terminal.messages = errors;
+                Err(String::from("Some buffers could not be written"))
+            }
         }
-        if should_quit {
-            self.buffers.remove(&curr_buff_key);
-            self.buff_vec.remove(self.current_buff_index);
-            self.current_buff_index = self.current_buff_index.saturating_sub(1);
+    }
+
+    fn close_buffer(&mut self, key: usize) {
+        if let Some(index) = self.buff_vec.iter().position(|k| *k == key) {
+            self.current_buff_index = index;
+            self.close_current_buffer();
         }
     }
 
-    fn buf_prev(&mut self) {
-        if self.current_buff_index == 0 {
-            self.current_buff_index = self.buff_vec.len().saturating_sub(1);
+    // a file that could not be read is reported, or offered in another view when it is not
+    // UTF-8 and the buffer has no changes a view would drop, true when there was an error and
+    // the buffer is read-only
+    fn report_open_error(&mut self, key: usize) -> bool {
+        let Some(buffer) = self.buffers.get_mut(&key) else {
+            return false;
+        };
+        let Some(error) = buffer.open_error.take() else {
+            return false;
+        };
+        match error {
+            FileError::InvalidUtf8(..) if !buffer.is_changed => {
+                self.prompts.push_back(Prompt::view(key, &error))
+            }
+            error => self.command_mode.escape(&error.to_string()),
+        }
+        true
+    }
+
+    fn swap_dir(&self) -> Option<std::path::PathBuf> {
+        swap::directory(&self.options.get_string("directory"))
+    }
+
+    // gives a file buffer its swap file, asking first when another one is already there
+    fn attach_swap(&mut self, key: usize) {
+        let Some(buffer) = self.buffers.get(&key) else {
+            return;
+        };
+        if !buffer.options.get_bool("swapfile") || buffer.swap.is_some() {
+            return;
+        }
+        let (Some(name), Some(dir)) = (buffer.filename.clone(), self.swap_dir()) else {
+            return;
+        };
+        if let Some(found) = swap::find(&dir, &name, None).into_iter().next() {
+            if self.recover {
+                if let Err(e) = self.recover_swap(key, found) {
+                    self.command_mode.escape(&e);
+                }
+            } else {
+                self.prompts.push_back(Prompt::swap(key, &name, found));
+            }
+            return;
+        }
+        self.create_swap(key, None);
+    }
+
+    fn create_swap(&mut self, key: usize, path: Option<std::path::PathBuf>) {
+        let Some(dir) = self.swap_dir() else {
+            return;
+        };
+        let Some(buffer) = self.buffers.get_mut(&key) else {
+            return;
+        };
+        let name = buffer.filename.clone().unwrap_or_default();
+        match SwapFile::create(&dir, &name, path) {
+            Ok(swap) => buffer.swap = Some(swap),
+            Err(e) => self.command_mode.escape(&format!(
+                "Unable to open swap file for \"{}\", recovery impossible: {}",
+                name, e
+            )),
+        }
+    }
+
+    // loads the text saved in swap, and keeps writing to it when its editor is gone
+    fn recover_swap(&mut self, key: usize, swap: SwapInfo) -> std::result::Result<(), String> {
+        let recovered = swap
+            .recover()
+            .map_err(|e| format!("{}: {}", swap.path.display(), e))?;
+        let buffer = self
+            .buffers
+            .get_mut(&key)
+            .ok_or(String::from("invalid buffer"))?;
+        let message = match recovered {
+            Some((rows, pos)) => {
+                buffer.restore(rows, pos);
+                String::from("Recovery completed. Check the changes, then write the file")
+            }
+            None => String::from("The swap file holds no changes, nothing to recover"),
+        };
+        if !swap.is_running() {
+            buffer.swap = None;
+            self.create_swap(key, Some(swap.path.clone()));
+        } else if buffer.swap.is_none() {
+            self.create_swap(key, None);
+        }
+        if let Some(buffer) = self.buffers.get_mut(&key) {
+            buffer.sync_swap();
+        }
+        self.command_mode.escape(&message);
+        Ok(())
+    }
+
+    // shows the question at the front of the queue, or nothing once all are answered
+    fn show_prompt(&mut self) {
+        match self.prompts.front() {
+            Some(prompt) => {
+                self.terminal.messages = prompt.lines.clone();
+                self.terminal.prompt = Some(prompt.question.clone());
+            }
+            None => self.terminal.prompt = None,
+        }
+    }
+
+    fn answer_prompt(&mut self, c: u8) {
+        let Some(answer) = self.prompts.front().and_then(|prompt| prompt.answer(c)) else {
+            return;
+        };
+        let prompt = self.prompts.pop_front().unwrap();
+        self.terminal.messages.clear();
+        self.command_mode.escape("");
+        match prompt.action {
+            PromptAction::Swap { buffer, swap } => match answer {
+                b'o' | b'e' => {
+                    if let Some(buf) = self.buffers.get_mut(&buffer)
+                        && answer == b'o'
+                        && let Some(def) = options::find("readonly")
+                    {
+                        buf.options.set(def, OptionValue::Bool(true));
+                    }
+                    self.create_swap(buffer, None);
+                }
+                b'r' => {
+                    if let Err(e) = self.recover_swap(buffer, swap) {
+                        self.command_mode.escape(&e);
+                    }
+                }
+                b'd' => {
+                    let _ = fs::remove_file(&swap.path);
+                    self.create_swap(buffer, None);
+                }
+                _ => self.close_buffer(buffer),
+            },
+            PromptAction::Reload { buffer } => {
+                if answer == b'l' {
+                    self.reload_buffer(buffer);
+                }
+            }
+            PromptAction::View { buffer } => match answer {
+                b'l' | b'h' => {
+                    if let Some(buf) = self.buffers.get_mut(&buffer)
+                        && let Err(e) = buf.load_view(answer == b'h')
+                    {
+                        self.command_mode.escape(&e.to_string());
+                    }
+                }
+                _ => self.close_buffer(buffer),
+            },
+        }
+        self.terminal.command_line = self.command_mode.command_string.clone();
+        self.show_prompt();
+    }
+
+    // reloads buffers whose file changed on disk when `autoread` is set and they have no changes
+    // of their own, and asks about the others
+    fn check_time(&mut self) {
+        self.last_checktime = Instant::now();
+        let mut keys: Vec<usize> = self.buffers.keys().copied().collect();
+        keys.sort();
+        for key in keys {
+            let buffer = self.buffers.get_mut(&key).unwrap();
+            let state = buffer.check_disk();
+            if state == DiskState::Deleted {
+                let name = buffer.filename.clone().unwrap_or_default();
+                self.command_mode
+                    .escape(&format!("File \"{}\" no longer available", name));
+                self.terminal.command_line = self.command_mode.command_string.clone();
+                continue;
+            }
+            if state == DiskState::Same
+                || self.prompts.iter().any(|prompt| {
+                    matches!(prompt.action, PromptAction::Reload { buffer } if buffer == key)
+                })
+            {
+                continue;
+            }
+            let name = buffer.filename.clone().unwrap_or_default();
+            if self.options.get_bool("autoread") && !buffer.is_changed {
+                self.reload_buffer(key);
+            } else {
+                let changed = buffer.is_changed;
+                self.prompts.push_back(Prompt::reload(key, &name, changed));
+            }
+        }
+        self.show_prompt();
+    }
+
+    fn reload_buffer(&mut self, key: usize) {
+        let Some(buffer) = self.buffers.get_mut(&key) else {
+            return;
+        };
+        let message = match buffer.reload(&self.options) {
+            Ok(()) => format!(
+                "\"{}\" reloaded from disk",
+                buffer.filename.clone().unwrap_or_default()
+            ),
+            Err(e) => e.to_string(),
+        };
+        self.command_mode.escape(&message);
+        self.terminal.command_line = self.command_mode.command_string.clone();
+    }
+
+    // writes the swap files after `updatecount` keys, or once typing pauses for `updatetime`
+    fn sync_swaps(&mut self, idle: bool) {
+        let count = self.options.get_number("updatecount");
+        let time = Duration::from_millis(self.options.get_number("updatetime") as u64);
+        let due = if idle {
+            self.keys_since_sync > 0 && self.last_key_time.elapsed() >= time
         } else {
-            self.current_buff_index = self.current_buff_index.saturating_sub(1);
+            count > 0 && self.keys_since_sync >= count
+        };
+        if !due {
+            return;
         }
+        self.keys_since_sync = 0;
+        for buffer in self.buffers.values_mut() {
+            buffer.sync_swap();
+        }
+    }
+
+    fn source_file(&mut self, path: &str) -> std::result::Result<(), String> {
+        if self.source_depth >= MAX_SOURCE_DEPTH {
+            return Err(String::from("Command too recursive"));
+        }
+        let commands = config::read_commands(&config::expand_home(path))
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let mut errors = Vec::new();
+        self.source_depth += 1;
+        for (line_no, line) in commands {
+            let command = self.command_mode.parse(&line);
+            if let Err(e) = self.run_command(command) {
+                errors.push(format!("{} line {}: {}", path, line_no, e));
+            }
+        }
+        self.source_depth -= 1;
         self.command_mode.escape("");
-        self.mode = EditorModes::Normal;
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => {
+                self.terminal.messages = errors;
+                Ok(())
+            }
+        }
     }
 
-    fn buf_n(&mut self, n: usize) {
-        match n.cmp(&self.buff_vec.len()) {
-            Ordering::Equal => {
-                let buffer = TextBuffer::new(None).unwrap();
-                let key = self.buff_vec.last().unwrap() + 1;
-                self.buff_vec.push(key);
-                self.buffers.insert(key, buffer);
-                self.current_buff_index = n;
-                self.command_mode.escape("");
+    fn fire_autocmds(&mut self, event: Event) {
+        if self.in_autocmd {
+            return;
+        }
+        let curr_buff_key = self.get_buff_key();
+        let filename = match self.buffers.get(&curr_buff_key) {
+            Some(buffer) => buffer.filename.clone().unwrap_or_default(),
+            None => return,
+        };
+        self.in_autocmd = true;
+        for line in self.autocmds.matching(event, &filename) {
+            let command = self.command_mode.parse(&line);
+            if let Err(e) = self.run_command(command) {
+                self.command_mode.escape(&e);
+                break;
             }
-            Ordering::Less => {
-                self.current_buff_index = n;
-                self.command_mode.escape("");
+        }
+        self.in_autocmd = false;
+    }
+
+    fn set_options(&mut self, args: &str, local: bool) -> std::result::Result<(), String> {
+        let mut shown = Vec::new();
+        let result = if args == "all" {
+            shown = options::OPTIONS
+                .iter()
+                .map(|def| def.format(&self.option_value(def)))
+                .collect();
+            Ok(())
+        } else if args.is_empty() {
+            shown = options::OPTIONS
+                .iter()
+                .filter(|def| self.option_value(def) != def.default_value())
+                .map(|def| def.format(&self.option_value(def)))
+                .collect();
+            Ok(())
+        } else {
+            args.split_whitespace()
+                .try_for_each(|arg| self.set_option(arg, local, &mut shown))
+        };
+        result?;
+        if shown.len() > 1 || args == "all" {
+            self.terminal.messages = Self::columns(&shown, self.terminal.size.x);
+        } else {
+            self.command_mode.escape(&shown.join(" "));
+        }
+        Ok(())
+    }
+
+    fn set_option(
+        &mut self,
+        arg: &str,
+        local: bool,
+        shown: &mut Vec<String>,
+    ) -> std::result::Result<(), String> {
+        let (name, action) = options::parse_set_arg(arg);
+        let def = options::find(name).ok_or(format!("Unknown option: {}", name))?;
+        let current = self.option_value(def);
+        let value = match (action, &current) {
+            (SetAction::Show, _) => {
+                shown.push(def.format(&current));
+                return Ok(());
             }
-            Ordering::Greater => {
-                self.command_mode.escape("Buffer not found");
+            (SetAction::Reset, _) => def.default_value(),
+            (SetAction::Enable, OptionValue::Bool(_)) => OptionValue::Bool(true),
+            (SetAction::Disable, OptionValue::Bool(_)) => OptionValue::Bool(false),
+            (SetAction::Toggle, OptionValue::Bool(b)) => OptionValue::Bool(!b),
+            (SetAction::Assign(value), _) if def.kind != OptionKind::Bool => def.parse(&value)?,
+            (SetAction::Add(value), OptionValue::Number(n)) => {
+                OptionValue::Number(n + value.parse::<usize>().map_err(|e| e.to_string())?)
             }
+            (SetAction::Remove(value), OptionValue::Number(n)) => OptionValue::Number(
+                n.saturating_sub(value.parse::<usize>().map_err(|e| e.to_string())?),
+            ),
+            (SetAction::Add(value), OptionValue::List(items)) => {
+                let mut items = items.clone();
+                items.extend(value.split(',').map(|item| item.to_string()));
+                OptionValue::List(items)
+            }
+            (SetAction::Remove(value), OptionValue::List(items)) => OptionValue::List(
+                items
+                    .iter()
+                    .filter(|item| **item != value)
+                    .cloned()
+                    .collect(),
+            ),
+            _ => return Err(format!("Invalid argument: {}", arg)),
+        };
+        def.validate(&value)?;
+        self.store_option(def, value, local);
+        Ok(())
+    }
+
+    fn option_value(&self, def: &OptionDef) -> OptionValue {
+        if def.scope == OptionScope::Buffer
+            && let Some(value) = self
+                .buff_vec
+                .get(self.current_buff_index)
+                .and_then(|key| self.buffers.get(key))
+                .and_then(|buffer| buffer.options.get(def.name))
+        {
+            return value.clone();
         }
-        self.mode = EditorModes::Normal;
+        self.options.get(def.name).unwrap().clone()
+    }
+
+    fn store_option(&mut self, def: &OptionDef, value: OptionValue, local: bool) {
+        if def.scope == OptionScope::Buffer {
+            if let Some(buffer) = self
+                .buff_vec
+                .get(self.current_buff_index)
+                .and_then(|key| self.buffers.get_mut(key))
+            {
+                // changing how the file is written back counts as a change to the buffer
+                if matches!(def.name, "fileformat" | "endofline" | "bomb")
+                    && buffer.options.get(def.name) != Some(&value)
+                {
+                    buffer.is_changed = true;
+                }
+                buffer.options.set(def, value.clone());
+                if def.name == "filetype" {
+                    buffer.set_filetype(&value.to_string());
+                }
+                if def.name == "swapfile" {
+                    buffer.swap = None;
+                    let key = self.get_buff_key();
+                    self.attach_swap(key);
+                    self.show_prompt();
+                }
+            }
+            if local {
+                return;
+            }
+        }
+        self.options.set(def, value);
+    }
+
+    fn columns(items: &[String], width: usize) -> Vec<String> {
+        let col_width = items.iter().map(|item| item.len()).max().unwrap_or(0) + 2;
+        let cols = (width / col_width).max(1);
+        let rows = items.len().div_ceil(cols);
+        (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .filter_map(|col| items.get(col * rows + row))
+                    .map(|item| format!("{:<1$}", item, col_width))
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn buf_prev(&mut self) {
+        let index = if self.current_buff_index == 0 {
+            self.buff_vec.len().saturating_sub(1)
+        } else {
+            self.current_buff_index.saturating_sub(1)
+        };
+        self.switch_buffer(index);
+    }
+
+    // buffer numbers are shown by `:ls`, the first buffer is 1
+    fn buffer_number(key: usize) -> usize {
+        key - 999
+    }
+
+    // the key of buffer number n
+    fn buffer_key(n: usize) -> usize {
+        n + 999
+    }
+
+    fn buf_n(&mut self, n: usize) -> std::result::Result<(), String> {
+        if let Some(name) = self.unlisted.get(&Self::buffer_key(n)).cloned() {
+            return self.show_file(&name);
+        }
+        let index = self
+            .buff_vec
+            .iter()
+            .position(|key| Self::buffer_number(*key) == n)
+            .ok_or(format!("Buffer {} does not exist", n))?;
+        self.switch_buffer(index);
+        Ok(())
     }
 
     fn buf_next(&mut self) {
-        if self.buff_vec.len().saturating_sub(1) == self.current_buff_index {
-            self.current_buff_index = 0;
+        let index = if self.buff_vec.len().saturating_sub(1) == self.current_buff_index {
+            0
         } else {
-            self.current_buff_index += 1;
+            self.current_buff_index + 1
+        };
+        self.switch_buffer(index);
+    }
+
+    fn buf_alternate(&mut self) -> std::result::Result<(), String> {
+        if let Some(name) = self
+            .alternate
+            .and_then(|key| self.unlisted.get(&key))
+            .cloned()
+        {
+            return self.show_file(&name);
         }
-        self.command_mode.escape("");
-        self.mode = EditorModes::Normal;
+        let index = self
+            .alternate
+            .and_then(|key| self.buff_vec.iter().position(|k| *k == key))
+            .ok_or(String::from("No alternate file"))?;
+        self.switch_buffer(index);
+        Ok(())
+    }
+
+    // makes the buffer at index current, the one left becomes the alternate
+    fn switch_buffer(&mut self, index: usize) {
+        if index != self.current_buff_index {
+            self.alternate = self.buff_vec.get(self.current_buff_index).copied();
+            self.current_buff_index = index;
+        }
+        self.fire_autocmds(Event::BufEnter);
+    }
+
+    // adds a buffer to the list in the order of the numbers and makes it current, a file
+    // closed with `:bd` gets its old number back
+    fn add_buffer(&mut self, buffer: TextBuffer) -> usize {
+        let path = buffer
+            .filename
+            .as_ref()
+            .and_then(|name| std::path::absolute(name).ok());
+        let reused = self.unlisted.iter().find_map(|(key, name)| {
+            (path.is_some() && std::path::absolute(name).ok() == path).then_some(*key)
+        });
+        let key = match reused {
+            Some(key) => {
+                self.unlisted.remove(&key);
+                key
+            }
+            None => {
+                self.next_key += 1;
+                self.next_key - 1
+            }
+        };
+        self.buffers.insert(key, buffer);
+        self.alternate = self.buff_vec.get(self.current_buff_index).copied();
+        let index = self.buff_vec.partition_point(|other| *other < key);
+        self.buff_vec.insert(index, key);
+        self.current_buff_index = index;
+        key
+    }
+
+    // the index of the buffer with number name, or with name in its file name
+    fn find_buffer(&self, name: &str) -> std::result::Result<usize, String> {
+        if let Ok(n) = name.parse::<usize>() {
+            return self
+                .buff_vec
+                .iter()
+                .position(|key| Self::buffer_number(*key) == n)
+                .ok_or(format!("Buffer {} does not exist", n));
+        }
+        let filename = |index: &usize| {
+            self.buffers[&self.buff_vec[*index]]
+                .filename
+                .clone()
+                .unwrap_or_default()
+        };
+        let indexes = 0..self.buff_vec.len();
+        // a full name wins over names that only contain it
+        if let Some(index) = indexes.clone().find(|index| filename(index) == name) {
+            return Ok(index);
+        }
+        let matches: Vec<usize> = indexes
+            .filter(|index| filename(index).contains(name))
+            .collect();
+        match matches[..] {
+            [index] => Ok(index),
+            [] => Err(format!("No matching buffer for {}", name)),
+            _ => Err(format!("More than one match for {}", name)),
+        }
+    }
+
+    // `:ls`: number, flags, name and cursor line of every buffer
+    fn list_buffers(&self) -> Vec<String> {
+        self.buff_vec
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                let buffer = &self.buffers[key];
+                let flag = if index == self.current_buff_index {
+                    '%'
+                } else if self.alternate == Some(*key) {
+                    '#'
+                } else {
+                    ' '
+                };
+                let name = format!("\"{}\"", buffer.filename.as_deref().unwrap_or("[No Name]"));
+                format!(
+                    "{:>3} {}{} {:<2} {:<30} line {}",
+                    Self::buffer_number(*key),
+                    flag,
+                    if buffer.is_changed { '+' } else { ' ' },
+                    if buffer.is_readonly() { "RO" } else { "" },
+                    name,
+                    buffer.pos.y + 1
+                )
+            })
+            .collect()
+    }
+
+    // `:bd`: closes a buffer but not the editor, the last one is replaced by an empty buffer;
+    // unless wiped, a buffer with a file keeps its number and can still be the alternate
+    fn delete_buffer(
+        &mut self,
+        index: usize,
+        force: bool,
+        wipe: bool,
+    ) -> std::result::Result<(), String> {
+        let key = self.buff_vec[index];
+        if self.buffers[&key].is_changed && !force {
+            return Err(format!(
+                "No write since last change for buffer {} (add ! to override)",
+                Self::buffer_number(key)
+            ));
+        }
+        if self.buff_vec.len() == 1 {
+            let buffer = TextBuffer::new(None, &self.options).map_err(|e| e.to_string())?;
+            self.add_buffer(buffer);
+        }
+        let current = self.buff_vec[self.current_buff_index];
+        let next = if current != key {
+            current
+        } else {
+            self.alternate
+                .filter(|alternate| *alternate != key && self.buffers.contains_key(alternate))
+                .unwrap_or_else(|| {
+                    let neighbour = if index + 1 < self.buff_vec.len() {
+                        index + 1
+                    } else {
+                        index - 1
+                    };
+                    self.buff_vec[neighbour]
+                })
+        };
+        let filename = self.buffers.remove(&key).and_then(|buffer| buffer.filename);
+        self.buff_vec.remove(index);
+        let kept = match filename {
+            Some(name) if !wipe => {
+                self.unlisted.insert(key, name);
+                true
+            }
+            _ => false,
+        };
+        if key == current && kept {
+            self.alternate = Some(key);
+        } else if self.alternate == Some(next) || (self.alternate == Some(key) && !kept) {
+            self.alternate = None;
+        }
+        self.current_buff_index = self.buff_vec.iter().position(|k| *k == next).unwrap();
+        if next != current {
+            self.fire_autocmds(Event::BufEnter);
+        }
+        Ok(())
     }
 
     fn process_keypress(&mut self) -> Result<()> {
         let c = self.terminal.read_key()?;
+        self.handle_key(c);
+        Ok(())
+    }
+
+    fn handle_key(&mut self, c: u8) {
+        if c == 0 {
+            self.sync_swaps(true);
+            if self.last_checktime.elapsed() >= Duration::from_secs(1) {
+                self.check_time();
+            }
+        } else {
+            self.keys_since_sync += 1;
+        }
+        if !self.prompts.is_empty() {
+            if c != 0 {
+                self.answer_prompt(c);
+            }
+            return;
+        }
+        if !self.terminal.messages.is_empty() {
+            if c == 0 {
+                return;
+            }
+            self.terminal.messages.clear();
+            if c != b':' {
+                return;
+            }
+        }
+        if c != 0 {
+            self.typeahead.push_back((c, true));
+            self.last_key_time = Instant::now();
+        }
+        self.process_typeahead();
+        self.sync_swaps(false);
+    }
+
+    fn map_mode(&self) -> MapMode {
+        match self.mode {
+            EditorModes::Normal => MapMode::Normal,
+            EditorModes::Insert => MapMode::Insert,
+            EditorModes::Command => MapMode::Command,
+        }
+    }
+
+    fn process_typeahead(&mut self) {
+        let mut expansions = 0;
+        while let Some(&(c, remap)) = self.typeahead.front() {
+            if remap {
+                let keys: Vec<u8> = self
+                    .typeahead
+                    .iter()
+                    .take_while(|(_, remap)| *remap)
+                    .map(|(c, _)| *c)
+                    .collect();
+                let mapping = match self.keymaps.lookup(self.map_mode(), &keys) {
+                    MapLookup::Pending(exact) => {
+                        let timeout = self.options.get_number("timeoutlen") as u64;
+                        if self.last_key_time.elapsed() < Duration::from_millis(timeout) {
+                            return;
+                        }
+                        exact
+                    }
+                    MapLookup::Match(len, rhs, noremap) => Some((len, rhs, noremap)),
+                    MapLookup::None => None,
+                };
+                if let Some((len, rhs, noremap)) = mapping {
+                    expansions += 1;
+                    if expansions > 1000 {
+                        self.typeahead.clear();
+                        self.command_mode.escape("recursive mapping");
+                        self.terminal.command_line = self.command_mode.command_string.clone();
+                        return;
+                    }
+                    let lhs: Vec<u8> = self.typeahead.drain(..len).map(|(c, _)| c).collect();
+                    for (i, key) in rhs.iter().enumerate().rev() {
+                        let remap = !(noremap || i < lhs.len() && rhs.starts_with(&lhs));
+                        self.typeahead.push_front((*key, remap));
+                    }
+                    continue;
+                }
+            }
+            self.typeahead.pop_front();
+            self.dispatch_key(c);
+            if self.buff_vec.is_empty() {
+                return;
+            }
+        }
+    }
+
+    fn dispatch_key(&mut self, c: u8) {
         match self.mode {
             EditorModes::Normal => {
                 self.process_normal_mode(c);
@@ -254,8 +2000,6 @@ impl Editor {
                 self.terminal.status_line_left = String::from(" Command ");
             }
         }
-
-        Ok(())
     }
 }
 impl Drop for Editor {
@@ -10,23 +10,48 @@ pub enum AppError {
     FileWriteError,
     ParseIntError(num::ParseIntError),
     EndOfInput,
+    ArgError(String),
 }
 #[derive(Debug)]
 pub enum FileError {
     EmptyFileName,
     FileChanged,
+    // reading or writing the named file failed
+    Io(String, io::Error),
+    // the named file is not UTF-8, from this line (1 based) on
+    InvalidUtf8(String, usize),
     OtherError(AppError),
 }
 impl fmt::Display for FileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             FileError::EmptyFileName => write!(f, "No filename"),
-            FileError::OtherError(_) => write!(f, "other error"),
+            FileError::OtherError(e) => write!(f, "{}", e),
             FileError::FileChanged => write!(f, "file changed"),
+            FileError::Io(name, e) => write!(f, "\"{}\": {}", name, io_reason(e)),
+            FileError::InvalidUtf8(name, line) => {
+                write!(f, "\"{}\": invalid UTF-8 at line {}", name, line)
+            }
         }
     }
 }
 
+// the os error without its number, in the words people search for
+fn io_reason(e: &io::Error) -> String {
+    let reason = match e.kind() {
+        io::ErrorKind::PermissionDenied => "permission denied",
+        io::ErrorKind::IsADirectory => "is a directory",
+        io::ErrorKind::NotADirectory => "not a directory",
+        io::ErrorKind::NotFound => "no such file or directory",
+        io::ErrorKind::StorageFull => "disk full",
+        io::ErrorKind::QuotaExceeded => "disk quota exceeded",
+        io::ErrorKind::FileTooLarge => "file too large",
+        io::ErrorKind::ReadOnlyFilesystem => "read-only file system",
+        _ => return e.to_string(),
+    };
+    reason.to_string()
+}
+
 impl fmt::Display for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -36,6 +61,7 @@ impl fmt::Display for AppError {
             AppError::EndOfInput => write!(f, "EndOFInputError"),
             AppError::ParseIntError(e) => write!(f, "ParseInt error {}", e),
             AppError::FileWriteError => write!(f, "File write error: ",),
+            AppError::ArgError(arg) => write!(f, "Argument missing after: {}", arg),
         }
     }
 }
@@ -1,29 +1,797 @@
-use crate::error::AppError;
+use crate::{error::FileError, rope::Rope};
+use memmap2::Mmap;
 use std::{
-    fs::File,
-    io::{self, BufRead, BufReader, Lines, Write},
+    ffi::CString,
+    fs::{self, File, Metadata, OpenOptions},
+    hash::{DefaultHasher, Hasher},
+    io::{self, BufWriter, Write},
+    mem,
+    ops::Range,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{MetadataExt, fchown},
+    },
     path::Path,
+    process, str,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
 };
+#[cfg(target_os = "linux")]
+use std::{os::fd::AsRawFd, ptr};
 
-pub fn load_file(filename: &str) -> Result<Vec<String>, AppError> {
-    let mut rows = Vec::new();
-    if let Ok(lines) = read_lines(filename) {
-        for line in lines {
-            rows.push(line?);
+// enough lines for the first screen, indexed before the file is shown
+const FIRST_LINES: usize = 1024;
+const CHUNK_LINES: usize = 16384;
+// bytes looked at to pick the fileformat
+const FORMAT_SAMPLE: usize = 65536;
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileFormat {
+    #[default]
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl FileFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+            FileFormat::Mac => "mac",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            "mac" => Some(FileFormat::Mac),
+            _ => None,
+        }
+    }
+
+    fn separator(self) -> &'static [u8] {
+        match self {
+            FileFormat::Unix => b"\n",
+            FileFormat::Dos => b"\r\n",
+            FileFormat::Mac => b"\r",
+        }
+    }
+
+    // the last byte of every line ending
+    fn terminator(self) -> u8 {
+        match self {
+            FileFormat::Mac => b'\r',
+            _ => b'\n',
+        }
+    }
+
+    // dos when every line ends in CR LF, mac when there are only lone CRs
+    fn detect(bytes: &[u8]) -> Self {
+        let mut newlines = bytes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'\n')
+            .peekable();
+        if newlines.peek().is_none() {
+            return if bytes.contains(&b'\r') {
+                FileFormat::Mac
+            } else {
+                FileFormat::Unix
+            };
+        }
+        if newlines.all(|(i, _)| i > 0 && bytes[i - 1] == b'\r') {
+            FileFormat::Dos
+        } else {
+            FileFormat::Unix
+        }
+    }
+}
+
+// how a file is laid out on disk, kept so it can be written back the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLayout {
+    pub format: FileFormat,
+    pub eol: bool,
+    pub bom: bool,
+}
+
+impl Default for FileLayout {
+    fn default() -> Self {
+        Self {
+            format: FileFormat::Unix,
+            eol: true,
+            bom: false,
+        }
+    }
+}
+
+impl FileLayout {
+    // the format is guessed from the start of the file, returns where the text begins
+    fn detect(bytes: &[u8]) -> (Self, usize) {
+        let bom = bytes.starts_with(BOM);
+        let start = if bom { BOM.len() } else { 0 };
+        let format = FileFormat::detect(&bytes[start..bytes.len().min(start + FORMAT_SAMPLE)]);
+        let eol = bytes.len() == start || bytes.last() == Some(&format.terminator());
+        (Self { format, eol, bom }, start)
+    }
+}
+
+fn read(filename: &str) -> Result<Vec<u8>, FileError> {
+    fs::read(filename).map_err(|e| FileError::Io(filename.to_string(), e))
+}
+
+// the contents of a file, to tell it from another of the same size
+pub fn hash_file(filename: &str) -> Option<u64> {
+    let bytes = fs::read(filename).ok()?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&bytes);
+    Some(hasher.finish())
+}
+
+// hashes what goes through it like hash_file hashes what it reads
+struct HashWriter<W> {
+    inner: W,
+    hasher: DefaultHasher,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.write(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// the layout is None when the file does not exist yet, the hash is that of hash_file
+pub fn load_file(filename: &str) -> Result<(Rope, Option<FileLayout>, u64), FileError> {
+    let bytes = match read(filename) {
+        Ok(bytes) => bytes,
+        Err(FileError::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok((Rope::new(), None, DefaultHasher::new().finish()));
+        }
+        Err(e) => return Err(e),
+    };
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&bytes);
+    let (layout, start) = FileLayout::detect(&bytes);
+    let mut lines = Vec::new();
+    let mut invalid = None;
+    split_lines(&bytes, start, layout.format, usize::MAX, |line, _| {
+        match str::from_utf8(&bytes[line]) {
+            Ok(text) => lines.push(text.to_string()),
+            Err(_) => invalid = Some(lines.len() + 1),
+        }
+        invalid.is_none()
+    });
+    if let Some(line) = invalid {
+        return Err(FileError::InvalidUtf8(filename.to_string(), line));
+    }
+    Ok((Rope::from(lines), Some(layout), hasher.finish()))
+}
+
+// the file with bytes that are not UTF-8 shown as U+FFFD
+pub fn load_lossy(filename: &str) -> Result<(Rope, FileLayout), FileError> {
+    let bytes = read(filename)?;
+    let (layout, start) = FileLayout::detect(&bytes);
+    let mut lines = Vec::new();
+    split_lines(&bytes, start, layout.format, usize::MAX, |line, _| {
+        lines.push(String::from_utf8_lossy(&bytes[line]).into_owned());
+        true
+    });
+    Ok((Rope::from(lines), layout))
+}
+
+// a hex dump of the file, 16 bytes a line with the printable ones on the right
+pub fn load_hex(filename: &str) -> Result<Rope, FileError> {
+    let bytes = read(filename)?;
+    Ok(bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut line = format!("{:08x}:", i * 16);
+            for pair in 0..8 {
+                line.push(' ');
+                for j in pair * 2..pair * 2 + 2 {
+                    match chunk.get(j) {
+                        Some(b) => line.push_str(&format!("{:02x}", b)),
+                        None => line.push_str("  "),
+                    }
+                }
+            }
+            line.push_str("  ");
+            line.extend(chunk.iter().map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            }));
+            line
+        })
+        .collect())
+}
+
+// false for an existing file this process may not write, a missing file counts as writable
+pub fn is_writable(filename: &str) -> bool {
+    let Ok(path) = CString::new(Path::new(filename).as_os_str().as_bytes()) else {
+        return true;
+    };
+    let writable = unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0;
+    writable || io::Error::last_os_error().raw_os_error() == Some(libc::ENOENT)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupCopy {
+    Yes,
+    No,
+    #[default]
+    Auto,
+}
+
+impl BackupCopy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "yes" => Some(BackupCopy::Yes),
+            "no" => Some(BackupCopy::No),
+            "auto" => Some(BackupCopy::Auto),
+            _ => None,
+        }
+    }
+}
+
+// how an existing file is replaced, backup holds the extension when one should be kept
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    pub backup: Option<String>,
+    pub copy: BackupCopy,
+}
+
+// writes to a temporary file that is renamed over the original, or overwrites the original
+// in place when that is the only way to keep its links or owner
+// returns the hash of what was written, the same as hash_file gives for it
+pub fn write_file_to_disk(
+    filename: &str,
+    rows: &mut Rope,
+    layout: &FileLayout,
+    options: &WriteOptions,
+) -> io::Result<u64> {
+    let path = Path::new(filename);
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink());
+    let target = if is_symlink {
+        fs::canonicalize(path)?
+    } else {
+        path.to_path_buf()
+    };
+    let original = fs::metadata(&target).ok();
+    // devices and fifos can only be written in place
+    let in_place = original.as_ref().is_some_and(|meta| match options.copy {
+        _ if !meta.is_file() => true,
+        BackupCopy::Yes => true,
+        BackupCopy::No => false,
+        BackupCopy::Auto => is_symlink || meta.nlink() > 1,
+    });
+    if let (Some(ext), Some(_)) = (&options.backup, &original) {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(ext);
+        make_backup(&target, Path::new(&backup), in_place)?;
+    }
+    // with auto, a directory that cannot take the new file or an owner that cannot be kept
+    // on it is a reason to write in place instead
+    let fallback = options.copy == BackupCopy::Auto;
+    if !in_place
+        && let Some(hash) = write_replacing(&target, rows, layout, original.as_ref(), fallback)?
+    {
+        return Ok(hash);
+    }
+    // rows may be mapped from this very file, which faults once it is truncated
+    rows.make_owned();
+    let mut file = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&target)?,
+    );
+    let hash = write_rows(&mut file, rows, layout)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(hash)
+}
+
+// the backup keeps the original's inode when the original is about to be replaced
+fn make_backup(target: &Path, backup: &Path, copy: bool) -> io::Result<()> {
+    let _ = fs::remove_file(backup);
+    if copy || fs::hard_link(target, backup).is_err() {
+        fs::copy(target, backup)?;
+    }
+    Ok(())
+}
+
+// None when the file should be written in place after all, only ever with fallback set
+fn write_replacing(
+    target: &Path,
+    rows: &Rope,
+    layout: &FileLayout,
+    original: Option<&Metadata>,
+    fallback: bool,
+) -> io::Result<Option<u64>> {
+    let dir = target
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{}.{}.tmp", name, process::id()));
+    let file = match OpenOptions::new().write(true).create_new(true).open(&temp) {
+        Ok(file) => file,
+        Err(_) if fallback => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let result = write_temp(file, target, rows, layout, original, fallback).and_then(|written| {
+        if written.is_some() {
+            fs::rename(&temp, target)?;
+            // the rename itself is only durable once the directory is synced
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(written)
+    });
+    if !matches!(result, Ok(Some(_))) {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_temp(
+    file: File,
+    target: &Path,
+    rows: &Rope,
+    layout: &FileLayout,
+    original: Option<&Metadata>,
+    fallback: bool,
+) -> io::Result<Option<u64>> {
+    if let Some(meta) = original {
+        if fchown(&file, Some(meta.uid()), Some(meta.gid())).is_err() && fallback {
+            return Ok(None);
+        }
+        file.set_permissions(meta.permissions())?;
+        copy_xattrs(target, &file);
+    }
+    let mut writer = BufWriter::new(file);
+    let hash = write_rows(&mut writer, rows, layout)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    Ok(Some(hash))
+}
+
+// returns the hash of what was written
+fn write_rows(file: &mut impl Write, rows: &Rope, layout: &FileLayout) -> io::Result<u64> {
+    let mut file = HashWriter {
+        inner: file,
+        hasher: DefaultHasher::new(),
+    };
+    if layout.bom {
+        file.write_all(BOM)?;
+    }
+    let separator = layout.format.separator();
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            file.write_all(separator)?;
+        }
+        file.write_all(row.as_bytes())?;
+    }
+    if layout.eol && !rows.is_empty() {
+        file.write_all(separator)?;
+    }
+    file.flush()?;
+    Ok(file.hasher.finish())
+}
+
+// extended attributes (SELinux labels, ACLs, ...) that cannot be set are skipped
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &File) {
+    let Ok(path) = CString::new(from.as_os_str().as_bytes()) else {
+        return;
+    };
+    let size = unsafe { libc::listxattr(path.as_ptr(), ptr::null_mut(), 0) };
+    if size <= 0 {
+        return;
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe { libc::listxattr(path.as_ptr(), names.as_mut_ptr().cast(), names.len()) };
+    if size <= 0 {
+        return;
+    }
+    for name in names[..size as usize]
+        .split(|&b| b == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let Ok(name) = CString::new(name) else {
+            continue;
+        };
+        let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+        if len < 0 {
+            continue;
+        }
+        let mut value = vec![0u8; len as usize];
+        let len = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if len < 0 {
+            continue;
+        }
+        unsafe {
+            libc::fsetxattr(
+                to.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                len as usize,
+                0,
+            );
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_from: &Path, _to: &File) {}
+
+// calls line with the range of each line from start and the offset of the next one, stops
+// after limit lines or when line returns false and returns where it stopped
+fn split_lines(
+    bytes: &[u8],
+    mut start: usize,
+    format: FileFormat,
+    limit: usize,
+    mut line: impl FnMut(Range<usize>, usize) -> bool,
+) -> usize {
+    let terminator = format.terminator();
+    let mut count = 0;
+    while start < bytes.len() && count < limit {
+        let end = bytes[start..]
+            .iter()
+            .position(|&b| b == terminator)
+            .map_or(bytes.len(), |i| start + i);
+        let next = (end + 1).min(bytes.len());
+        let text_end = if format == FileFormat::Dos && end > start && bytes[end - 1] == b'\r' {
+            end - 1
+        } else {
+            end
+        };
+        count += 1;
+        if !line(start..text_end, next) {
+            return next;
         }
+        start = next;
     }
-    Ok(rows)
+    start
+}
+
+enum Chunk {
+    Mapped(Vec<Range<usize>>),
+    // a line that is not valid UTF-8, the scan stops there
+    Invalid,
 }
-fn read_lines<P>(filename: P) -> Result<Lines<BufReader<File>>, AppError>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+
+// indexes the lines of a mapped file on a background thread, the editor picks them up with poll
+pub struct FileLoader {
+    map: Arc<Mmap>,
+    receiver: Receiver<(Chunk, usize)>,
+    done: usize,
+    // the first line that is not UTF-8, 1 based
+    invalid: Option<usize>,
 }
-pub fn write_file_to_disk(filename: &str, rows: &[String]) -> Result<(), AppError> {
-    let long_string = rows.join("\n");
-    let mut file = File::create(filename)?;
-    file.write_all(long_string.as_bytes())?;
+
+impl FileLoader {
+    pub fn progress(&self) -> usize {
+        self.done * 100 / self.map.len().max(1)
+    }
+
+    pub fn invalid(&self) -> Option<usize> {
+        self.invalid
+    }
+
+    fn add(&mut self, rows: &mut Rope, chunk: Chunk, done: usize) {
+        if let Err(line) = add_chunk(rows, &self.map, chunk) {
+            self.invalid = Some(line);
+        }
+        self.done = done;
+    }
+
+    // adds whatever has been indexed so far, false once the whole file is in rows
+    pub fn poll(&mut self, rows: &mut Rope) -> bool {
+        loop {
+            match self.receiver.try_recv() {
+                Ok((chunk, done)) => self.add(rows, chunk, done),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    // blocks until the rest of the file is in rows
+    pub fn finish(&mut self, rows: &mut Rope) {
+        while let Ok((chunk, done)) = self.receiver.recv() {
+            self.add(rows, chunk, done);
+        }
+    }
+}
+
+// the number of the line that is not UTF-8 when the chunk marks one
+fn add_chunk(rows: &mut Rope, map: &Arc<Mmap>, chunk: Chunk) -> Result<(), usize> {
+    match chunk {
+        Chunk::Mapped(lines) => rows.append_mapped(map, &lines),
+        Chunk::Invalid => return Err(rows.len() + 1),
+    }
     Ok(())
 }
+
+// hands the lines from start to emit in chunks, like split_lines, up to the first line
+// that is not UTF-8
+fn scan(
+    bytes: &[u8],
+    start: usize,
+    format: FileFormat,
+    limit: usize,
+    mut emit: impl FnMut(Chunk, usize) -> bool,
+) -> usize {
+    let mut lines = Vec::new();
+    let end = split_lines(bytes, start, format, limit, |line, next| {
+        if str::from_utf8(&bytes[line.clone()]).is_err() {
+            if !lines.is_empty() && !emit(Chunk::Mapped(mem::take(&mut lines)), next) {
+                return false;
+            }
+            emit(Chunk::Invalid, next);
+            return false;
+        }
+        lines.push(line);
+        lines.len() < CHUNK_LINES || emit(Chunk::Mapped(mem::take(&mut lines)), next)
+    });
+    if !lines.is_empty() {
+        emit(Chunk::Mapped(lines), end);
+    }
+    end
+}
+
+// maps the file and returns its first screen of lines straight away, with a loader for the rest
+pub fn map_file(filename: &str) -> Result<(Rope, FileLayout, Option<FileLoader>), FileError> {
+    let error = |e| FileError::Io(filename.to_string(), e);
+    let file = File::open(filename).map_err(error)?;
+    // the map is only read, but a file truncated by another process while it is open can
+    // still fault, the same trade-off every mmap based viewer makes
+    let map = Arc::new(unsafe { Mmap::map(&file).map_err(error)? });
+    let (layout, start) = FileLayout::detect(&map);
+    let mut rows = Rope::new();
+    let mut invalid = None;
+    let start = scan(&map, start, layout.format, FIRST_LINES, |chunk, _| {
+        invalid = add_chunk(&mut rows, &map, chunk).err();
+        true
+    });
+    if let Some(line) = invalid {
+        return Err(FileError::InvalidUtf8(filename.to_string(), line));
+    }
+    if start >= map.len() {
+        return Ok((rows, layout, None));
+    }
+    let (sender, receiver) = mpsc::channel();
+    let bytes = map.clone();
+    thread::spawn(move || {
+        scan(&bytes, start, layout.format, usize::MAX, |chunk, done| {
+            sender.send((chunk, done)).is_ok()
+        });
+    });
+    Ok((
+        rows,
+        layout,
+        Some(FileLoader {
+            map,
+            receiver,
+            done: start,
+            invalid: None,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    // a path of its own for each test, removed when dropped
+    struct Temp(PathBuf);
+
+    impl Temp {
+        fn new(name: &str) -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let count = COUNT.fetch_add(1, Ordering::Relaxed);
+            let name = format!("rusty-vim-{}-{}-{}", process::id(), count, name);
+            let path = std::env::temp_dir().join(name);
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+
+        fn name(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for Temp {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn numbered(count: usize) -> String {
+        (0..count).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    fn map_all(filename: &str) -> (Rope, FileLayout) {
+        let (mut rows, layout, loader) = map_file(filename).unwrap();
+        if let Some(mut loader) = loader {
+            loader.finish(&mut rows);
+        }
+        (rows, layout)
+    }
+
+    #[test]
+    fn mapped_file_written_in_place() {
+        let file = Temp::new("in-place");
+        let text = numbered(5000);
+        fs::write(&file.0, &text).unwrap();
+        let (mut rows, layout) = map_all(file.name());
+        assert!(rows.is_mapped());
+        let options = WriteOptions {
+            backup: None,
+            copy: BackupCopy::Yes,
+        };
+        let hash = write_file_to_disk(file.name(), &mut rows, &layout, &options).unwrap();
+        assert_eq!(Some(hash), hash_file(file.name()));
+        assert!(!rows.is_mapped());
+        assert_eq!(fs::read_to_string(&file.0).unwrap(), text);
+        assert_eq!(rows.get(4999), Some("line 4999"));
+    }
+
+    // auto keeps the links by writing in place, which must not read the truncated map
+    #[test]
+    fn mapped_file_with_links_written_in_place() {
+        let file = Temp::new("linked");
+        let hard = Temp::new("linked-hard");
+        let soft = Temp::new("linked-soft");
+        let text = numbered(5000);
+        fs::write(&file.0, &text).unwrap();
+        fs::hard_link(&file.0, &hard.0).unwrap();
+        std::os::unix::fs::symlink(&file.0, &soft.0).unwrap();
+        for name in [hard.name(), soft.name()] {
+            let (mut rows, layout) = map_all(name);
+            write_file_to_disk(name, &mut rows, &layout, &WriteOptions::default()).unwrap();
+            assert!(!rows.is_mapped());
+            assert_eq!(fs::read_to_string(&file.0).unwrap(), text);
+            assert_eq!(fs::read_to_string(&hard.0).unwrap(), text);
+        }
+        assert_eq!(fs::metadata(&file.0).unwrap().nlink(), 2);
+        assert!(
+            fs::symlink_metadata(&soft.0)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+    }
+
+    // replacing the file leaves the map on the old one, so the lines can stay mapped
+    #[test]
+    fn mapped_file_replaced() {
+        let file = Temp::new("replaced");
+        let text = numbered(5000);
+        fs::write(&file.0, &text).unwrap();
+        let (mut rows, layout) = map_all(file.name());
+        let hash =
+            write_file_to_disk(file.name(), &mut rows, &layout, &WriteOptions::default()).unwrap();
+        assert_eq!(Some(hash), hash_file(file.name()));
+        assert!(rows.is_mapped());
+        assert_eq!(fs::read_to_string(&file.0).unwrap(), text);
+    }
+
+    #[test]
+    fn mapped_file_not_utf8() {
+        let file = Temp::new("latin1");
+        fs::write(&file.0, b"caf\xe9\nok\n").unwrap();
+        assert!(matches!(
+            map_file(file.name()),
+            Err(FileError::InvalidUtf8(_, 1))
+        ));
+        // past the first screen the bad line is found by the loader
+        let mut bytes = numbered(5000).into_bytes();
+        bytes.extend(b"caf\xe9\n");
+        fs::write(&file.0, bytes).unwrap();
+        let (mut rows, _, loader) = map_file(file.name()).unwrap();
+        let mut loader = loader.unwrap();
+        loader.finish(&mut rows);
+        assert_eq!(loader.invalid(), Some(5001));
+        assert_eq!(rows.len(), 5000);
+    }
+
+    // the file read and written back with layout, changed by adjust
+    fn round_trip(bytes: &[u8], adjust: impl FnOnce(&mut FileLayout)) -> (FileLayout, Vec<u8>) {
+        let file = Temp::new("round-trip");
+        fs::write(&file.0, bytes).unwrap();
+        let (rows, layout, hash) = load_file(file.name()).unwrap();
+        assert_eq!(Some(hash), hash_file(file.name()));
+        let mut layout = layout.unwrap();
+        let detected = layout;
+        adjust(&mut layout);
+        let mut out = Vec::new();
+        write_rows(&mut out, &rows, &layout).unwrap();
+        (detected, out)
+    }
+
+    #[test]
+    fn formats_written_back_unchanged() {
+        for (bytes, format) in [
+            (&b"one\ntwo\n\nfour\n"[..], FileFormat::Unix),
+            (b"one\r\ntwo\r\n\r\nfour\r\n", FileFormat::Dos),
+            (b"one\rtwo\r\rfour\r", FileFormat::Mac),
+            (b"", FileFormat::Unix),
+        ] {
+            let (layout, out) = round_trip(bytes, |_| ());
+            assert_eq!(layout.format, format);
+            assert_eq!(out, bytes);
+        }
+    }
+
+    #[test]
+    fn mixed_line_endings_kept() {
+        // a lone LF makes it unix, the CRs stay part of their lines
+        let bytes = b"one\r\ntwo\nthree\rstill three\r\n";
+        let (layout, out) = round_trip(bytes, |_| ());
+        assert_eq!(layout.format, FileFormat::Unix);
+        assert_eq!(out, bytes);
+    }
+
+    #[test]
+    fn missing_final_newline() {
+        for (bytes, fixed) in [
+            (&b"one\ntwo"[..], &b"one\ntwo\n"[..]),
+            (b"one\r\ntwo", b"one\r\ntwo\r\n"),
+            (b"one\rtwo", b"one\rtwo\r"),
+        ] {
+            // nofixeol keeps the file as it was
+            let (layout, out) = round_trip(bytes, |_| ());
+            assert!(!layout.eol);
+            assert_eq!(out, bytes);
+            // fixeol, the default, adds the line ending
+            let (_, out) = round_trip(bytes, |layout| layout.eol = true);
+            assert_eq!(out, fixed);
+        }
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let bytes = b"\xef\xbb\xbfone\r\ntwo\r\n";
+        let (layout, out) = round_trip(bytes, |_| ());
+        assert!(layout.bom);
+        assert_eq!(layout.format, FileFormat::Dos);
+        assert_eq!(out, bytes);
+        let (_, out) = round_trip(bytes, |layout| layout.bom = false);
+        assert_eq!(out, &bytes[BOM.len()..]);
+        let (_, out) = round_trip(b"one\n", |layout| layout.bom = true);
+        assert_eq!(out, b"\xef\xbb\xbfone\n");
+    }
+}
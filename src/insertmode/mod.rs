@@ -2,6 +2,7 @@ pub enum InsertAction {
     Backspace,
     Newline,
     Escape,
+    Literal,
     None,
     Chars(u8),
 }
@@ -22,6 +23,7 @@ impl InsertAction {
             127 => InsertAction::Backspace,
             13 => InsertAction::Newline,
             9 => InsertAction::Chars(c),
+            22 => InsertAction::Literal,
             c if c >= 32 => InsertAction::Chars(c),
             _ => InsertAction::None,
         }
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapMode {
+    Normal,
+    Insert,
+    Command,
+}
+
+impl MapMode {
+    fn prefix(&self) -> char {
+        match self {
+            MapMode::Normal => 'n',
+            MapMode::Insert => 'i',
+            MapMode::Command => 'c',
+        }
+    }
+}
+
+struct Mapping {
+    lhs: Vec<u8>,
+    rhs: Vec<u8>,
+    noremap: bool,
+}
+
+pub enum MapLookup {
+    None,
+    Pending(Option<(usize, Vec<u8>, bool)>),
+    Match(usize, Vec<u8>, bool),
+}
+
+#[derive(Default)]
+pub struct KeyMaps {
+    maps: HashMap<MapMode, Vec<Mapping>>,
+}
+
+impl KeyMaps {
+    pub fn add(&mut self, modes: &[MapMode], lhs: Vec<u8>, rhs: Vec<u8>, noremap: bool) {
+        for mode in modes {
+            let maps = self.maps.entry(*mode).or_default();
+            maps.retain(|mapping| mapping.lhs != lhs);
+            maps.push(Mapping {
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+                noremap,
+            });
+        }
+    }
+
+    pub fn remove(&mut self, modes: &[MapMode], lhs: &[u8]) -> Result<(), String> {
+        let mut found = false;
+        for mode in modes {
+            if let Some(maps) = self.maps.get_mut(mode) {
+                let len = maps.len();
+                maps.retain(|mapping| mapping.lhs != lhs);
+                found |= maps.len() != len;
+            }
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(String::from("No such mapping"))
+        }
+    }
+
+    pub fn list(&self, modes: &[MapMode], lhs: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for mode in modes {
+            for mapping in self.maps.get(mode).into_iter().flatten() {
+                if mapping.lhs.starts_with(lhs) {
+                    lines.push(format!(
+                        "{}  {:<12} {}{}",
+                        mode.prefix(),
+                        key_notation(&mapping.lhs),
+                        if mapping.noremap { '*' } else { ' ' },
+                        key_notation(&mapping.rhs)
+                    ));
+                }
+            }
+        }
+        lines
+    }
+
+    pub fn lookup(&self, mode: MapMode, keys: &[u8]) -> MapLookup {
+        let maps = match self.maps.get(&mode) {
+            Some(maps) if !keys.is_empty() => maps,
+            _ => return MapLookup::None,
+        };
+        let exact = maps
+            .iter()
+            .filter(|mapping| keys.starts_with(&mapping.lhs))
+            .max_by_key(|mapping| mapping.lhs.len())
+            .map(|mapping| (mapping.lhs.len(), mapping.rhs.clone(), mapping.noremap));
+        let is_prefix = maps
+            .iter()
+            .any(|mapping| mapping.lhs.len() > keys.len() && mapping.lhs.starts_with(keys));
+        match exact {
+            _ if is_prefix => MapLookup::Pending(exact),
+            Some((len, rhs, noremap)) => MapLookup::Match(len, rhs, noremap),
+            None => MapLookup::None,
+        }
+    }
+}
+
+pub fn parse_keys(notation: &str, leader: &[u8]) -> Vec<u8> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        if c == '<'
+            && let Some(end) = rest.find('>')
+            && let Some(key) = special_key(&rest[1..end], leader)
+        {
+            keys.extend(key);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let mut buf = [0; 4];
+        keys.extend(c.encode_utf8(&mut buf).as_bytes());
+        rest = &rest[c.len_utf8()..];
+    }
+    keys
+}
+
+fn special_key(name: &str, leader: &[u8]) -> Option<Vec<u8>> {
+    let key = match name.to_lowercase().as_str() {
+        "cr" | "enter" | "return" => 13,
+        "esc" => 27,
+        "tab" => 9,
+        "bs" => 127,
+        "space" => b' ',
+        "lt" => b'<',
+        "bar" => b'|',
+        "bslash" => b'\\',
+        "nop" => return Some(Vec::new()),
+        "leader" => return Some(leader.to_vec()),
+        lower => {
+            let c = lower.strip_prefix("c-")?;
+            match c.as_bytes() {
+                [c @ b'a'..=b'z'] => c & 0x1f,
+                [b'['] => 27,
+                _ => return None,
+            }
+        }
+    };
+    Some(vec![key])
+}
+
+pub fn key_notation(keys: &[u8]) -> String {
+    let mut notation = String::new();
+    for key in String::from_utf8_lossy(keys).chars() {
+        match key {
+            '\r' => notation.push_str("<CR>"),
+            '\x1b' => notation.push_str("<Esc>"),
+            '\t' => notation.push_str("<Tab>"),
+            '\x7f' => notation.push_str("<BS>"),
+            ' ' => notation.push_str("<Space>"),
+            c if (c as u32) < 32 => {
+                notation.push_str(&format!("<C-{}>", (c as u8 + b'a' - 1) as char))
+            }
+            c => notation.push(c),
+        }
+    }
+    notation
+}
@@ -0,0 +1,39 @@
+//! Rusty Vim as a library: drive an [`Editor`] with your own [`Backend`], feed it keys,
+//! run Ex commands and read back the buffers, or use [`TextBuffer`] and its motions directly.
+
+mod abbrev;
+mod args;
+mod autocmd;
+mod backend;
+mod buffer;
+mod commandmode;
+mod config;
+mod editor;
+mod error;
+mod file;
+mod insertmode;
+mod keymap;
+mod normalmode;
+mod options;
+mod prompt;
+mod rope;
+mod screen;
+mod swap;
+mod syntax;
+mod terminal;
+mod theme;
+mod window;
+
+pub use backend::headless::HeadlessBackend;
+pub use backend::termios::TermiosBackend;
+pub use backend::{Backend, CursorShape};
+pub use buffer::{DiskState, TextBuffer};
+pub use editor::{Editor, EditorModes};
+pub use error::{AppError, FileError, Result};
+pub use insertmode::InsertType;
+pub use normalmode::motions::Motion;
+pub use rope::Rope;
+pub use screen::{Cell, Screen};
+pub use swap::recoverable_files;
+pub use terminal::Position;
+pub use theme::{Group, Theme};
@@ -1,18 +1,16 @@
-mod buffer;
-pub mod commandmode;
-mod editor;
-mod error;
-mod file;
-mod insertmode;
-mod normalmode;
-mod terminal;
+use rusty_vim::{Editor, Result};
 use std::env;
 
-use crate::editor::Editor;
-use crate::error::Result;
-
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    Editor::new(args)?.run()?;
+    if args.len() == 2 && args[1] == "-r" {
+        print!("{}", rusty_vim::recoverable_files());
+        return Ok(());
+    }
+    let mut editor = Editor::new(args)?;
+    editor.run()?;
+    if let Some(lines) = editor.headless_screen() {
+        println!("{}", lines.join("\n"));
+    }
     Ok(())
 }
@@ -21,6 +21,19 @@ impl NormalMode {
         Self { pending_operations }
     }
     pub fn handle_keypress(&mut self, c: u8) -> Result<BufferAction, NormalKeyError> {
+        // ctrl-w ctrl-h is ctrl-w h
+        let c = match c {
+            1..32 if self.pending_operations.action == '\x17' => c + 96,
+            c => c,
+        };
+        if c == 0x17 {
+            self.pending_operations.insert_key(c as char);
+            return Err(NormalKeyError::NotMotion);
+        }
+        if c == 0x1e {
+            let count = self.pending_operations.repeat;
+            return Ok(BufferAction::SwitchBuffer((count != 0).then_some(count)));
+        }
         if c < 32 {
             return Err(NormalKeyError::InvalidKey);
         }
@@ -56,6 +69,19 @@ impl NormalMode {
                 },
                 'g' => match self.pending_operations.motion {
                     'g' => BufferAction::Move(Motion::GoToLine(repeat.saturating_sub(1))),
+                    't' | 'T' => BufferAction::Tab(
+                        self.pending_operations.motion,
+                        self.pending_operations.repeat,
+                    ),
+                    _ => BufferAction::None,
+                },
+                '\x17' => BufferAction::Window(
+                    self.pending_operations.motion,
+                    self.pending_operations.repeat,
+                ),
+                'Z' => match self.pending_operations.motion {
+                    'Z' => BufferAction::Command("x"),
+                    'Q' => BufferAction::Command("q!"),
                     _ => BufferAction::None,
                 },
                 _ => BufferAction::None,
@@ -24,4 +24,12 @@ pub enum BufferAction {
     ChangeMode(EditorModes, InsertType),
     None,
     Move(Motion),
+    // ctrl-^: buffer N with a count, the alternate buffer without
+    SwitchBuffer(Option<usize>),
+    // an Ex command run by a normal mode key, like ZZ
+    Command(&'static str),
+    // ctrl-w and the key after it, with the count or 0
+    Window(char, usize),
+    // gt or gT, with the count or 0
+    Tab(char, usize),
 }
@@ -11,13 +11,14 @@ pub struct PendingOperations {
 
 impl PendingOperations {
     pub fn new() -> PendingOperations {
-        let keys_action = ['d', 'f', 'g'];
+        let keys_action = ['d', 'f', 'g', 'Z', '\x17'];
         let valid_actions: HashSet<char> = keys_action.iter().cloned().collect();
         let keys_modifier = ['i', 'a', 'f'];
         let valid_modifiers: HashSet<char> = keys_modifier.iter().cloned().collect();
         let keys_motion = [
             'h', 'j', 'k', 'l', 'x', 'd', 'g', 'G', 'a', 'I', 'i', 'A', 'o', 'O', 'w', 'W', '{',
-            '}', ':', '^', '$', '0', '\x7F',
+            '}', ':', '^', '$', '0', '\x7F', 'Z', 'Q', 'c', 's', 'v', 'q', '=', '+', '-', '<', '>',
+            't', 'T',
         ];
         let valid_motions: HashSet<char> = keys_motion.iter().cloned().collect();
         Self {
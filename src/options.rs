@@ -0,0 +1,410 @@
+use crate::{
+    file::{BackupCopy, FileFormat},
+    terminal::ListChars,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    Bool,
+    Number,
+    String,
+    List,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionScope {
+    Global,
+    Buffer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Bool(bool),
+    Number(usize),
+    String(String),
+    List(Vec<String>),
+}
+
+pub struct OptionDef {
+    pub name: &'static str,
+    pub short: &'static str,
+    pub kind: OptionKind,
+    pub scope: OptionScope,
+    default: &'static str,
+}
+
+pub const OPTIONS: &[OptionDef] = &[
+    OptionDef {
+        name: "autoread",
+        short: "ar",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "backup",
+        short: "bk",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "backupcopy",
+        short: "bkc",
+        kind: OptionKind::String,
+        scope: OptionScope::Global,
+        default: "auto",
+    },
+    OptionDef {
+        name: "backupext",
+        short: "bex",
+        kind: OptionKind::String,
+        scope: OptionScope::Global,
+        default: "~",
+    },
+    OptionDef {
+        name: "bomb",
+        short: "bomb",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "false",
+    },
+    OptionDef {
+        name: "colorcolumn",
+        short: "cc",
+        kind: OptionKind::String,
+        scope: OptionScope::Global,
+        default: "",
+    },
+    OptionDef {
+        name: "cursorline",
+        short: "cul",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "true",
+    },
+    OptionDef {
+        name: "directory",
+        short: "dir",
+        kind: OptionKind::String,
+        scope: OptionScope::Global,
+        default: "",
+    },
+    OptionDef {
+        name: "endofline",
+        short: "eol",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "true",
+    },
+    OptionDef {
+        name: "expandtab",
+        short: "et",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "true",
+    },
+    OptionDef {
+        name: "fileformat",
+        short: "ff",
+        kind: OptionKind::String,
+        scope: OptionScope::Buffer,
+        default: "unix",
+    },
+    OptionDef {
+        name: "filetype",
+        short: "ft",
+        kind: OptionKind::String,
+        scope: OptionScope::Buffer,
+        default: "",
+    },
+    OptionDef {
+        name: "fixendofline",
+        short: "fixeol",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "true",
+    },
+    OptionDef {
+        name: "largefile",
+        short: "lf",
+        kind: OptionKind::Number,
+        scope: OptionScope::Global,
+        default: "100",
+    },
+    OptionDef {
+        name: "list",
+        short: "list",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "listchars",
+        short: "lcs",
+        kind: OptionKind::List,
+        scope: OptionScope::Global,
+        default: "eol:$",
+    },
+    OptionDef {
+        name: "mapleader",
+        short: "mapleader",
+        kind: OptionKind::String,
+        scope: OptionScope::Global,
+        default: "\\",
+    },
+    OptionDef {
+        name: "modifiable",
+        short: "ma",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "true",
+    },
+    OptionDef {
+        name: "number",
+        short: "nu",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "true",
+    },
+    OptionDef {
+        name: "readonly",
+        short: "ro",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "false",
+    },
+    OptionDef {
+        name: "softtabstop",
+        short: "sts",
+        kind: OptionKind::Number,
+        scope: OptionScope::Buffer,
+        default: "4",
+    },
+    OptionDef {
+        name: "swapfile",
+        short: "swf",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Buffer,
+        default: "true",
+    },
+    OptionDef {
+        name: "syntax",
+        short: "syn",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "true",
+    },
+    OptionDef {
+        name: "tabstop",
+        short: "ts",
+        kind: OptionKind::Number,
+        scope: OptionScope::Buffer,
+        default: "8",
+    },
+    OptionDef {
+        name: "termguicolors",
+        short: "tgc",
+        kind: OptionKind::Bool,
+        scope: OptionScope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "timeoutlen",
+        short: "tm",
+        kind: OptionKind::Number,
+        scope: OptionScope::Global,
+        default: "1000",
+    },
+    OptionDef {
+        name: "updatecount",
+        short: "uc",
+        kind: OptionKind::Number,
+        scope: OptionScope::Global,
+        default: "200",
+    },
+    OptionDef {
+        name: "updatetime",
+        short: "ut",
+        kind: OptionKind::Number,
+        scope: OptionScope::Global,
+        default: "4000",
+    },
+];
+
+pub enum SetAction {
+    Show,
+    Enable,
+    Disable,
+    Toggle,
+    Reset,
+    Assign(String),
+    Add(String),
+    Remove(String),
+}
+
+pub fn find(name: &str) -> Option<&'static OptionDef> {
+    OPTIONS
+        .iter()
+        .find(|def| def.name == name || def.short == name)
+}
+
+pub fn parse_set_arg(arg: &str) -> (&str, SetAction) {
+    if let Some((name, value)) = arg.split_once(['=', ':']) {
+        if let Some(name) = name.strip_suffix('+') {
+            return (name, SetAction::Add(value.to_string()));
+        }
+        if let Some(name) = name.strip_suffix('-') {
+            return (name, SetAction::Remove(value.to_string()));
+        }
+        return (name, SetAction::Assign(value.to_string()));
+    }
+    if let Some(name) = arg.strip_suffix('?') {
+        return (name, SetAction::Show);
+    }
+    if let Some(name) = arg.strip_suffix('&') {
+        return (name, SetAction::Reset);
+    }
+    if let Some(name) = arg.strip_suffix('!') {
+        return (name, SetAction::Toggle);
+    }
+    if find(arg).is_none() {
+        if let Some(name) = arg.strip_prefix("no") {
+            return (name, SetAction::Disable);
+        }
+        if let Some(name) = arg.strip_prefix("inv") {
+            return (name, SetAction::Toggle);
+        }
+    }
+    match find(arg) {
+        Some(def) if def.kind == OptionKind::Bool => (arg, SetAction::Enable),
+        _ => (arg, SetAction::Show),
+    }
+}
+
+impl OptionDef {
+    pub fn default_value(&self) -> OptionValue {
+        self.parse(self.default).unwrap()
+    }
+
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        let parsed = match self.kind {
+            OptionKind::Bool => match value {
+                "true" => OptionValue::Bool(true),
+                "false" => OptionValue::Bool(false),
+                _ => return Err(format!("Invalid argument: {}={}", self.name, value)),
+            },
+            OptionKind::Number => match value.parse::<usize>() {
+                Ok(n) => OptionValue::Number(n),
+                Err(_) => return Err(format!("Number required after =: {}={}", self.name, value)),
+            },
+            OptionKind::String => OptionValue::String(value.to_string()),
+            OptionKind::List => OptionValue::List(
+                value
+                    .split(',')
+                    .filter(|item| !item.is_empty())
+                    .map(|item| item.to_string())
+                    .collect(),
+            ),
+        };
+        self.validate(&parsed)?;
+        Ok(parsed)
+    }
+
+    pub fn validate(&self, value: &OptionValue) -> Result<(), String> {
+        let valid = match (self.name, value) {
+            ("tabstop", OptionValue::Number(n)) => *n > 0,
+            ("fileformat", OptionValue::String(format)) => FileFormat::from_name(format).is_some(),
+            ("backupcopy", OptionValue::String(copy)) => BackupCopy::from_name(copy).is_some(),
+            ("backupext", OptionValue::String(ext)) => !ext.is_empty() && !ext.contains('/'),
+            ("colorcolumn", OptionValue::String(cols)) => cols
+                .split(',')
+                .filter(|col| !col.is_empty())
+                .all(|col| col.parse::<usize>().is_ok_and(|col| col > 0)),
+            ("listchars", OptionValue::List(items)) => ListChars::parse(&items.join(",")).is_some(),
+            _ => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid argument: {}={}", self.name, value))
+        }
+    }
+
+    pub fn format(&self, value: &OptionValue) -> String {
+        match value {
+            OptionValue::Bool(true) => self.name.to_string(),
+            OptionValue::Bool(false) => format!("no{}", self.name),
+            _ => format!("{}={}", self.name, value),
+        }
+    }
+}
+
+impl std::fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionValue::Bool(b) => write!(f, "{}", b),
+            OptionValue::Number(n) => write!(f, "{}", n),
+            OptionValue::String(s) => write!(f, "{}", s),
+            OptionValue::List(items) => write!(f, "{}", items.join(",")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    values: HashMap<&'static str, OptionValue>,
+}
+
+impl Options {
+    pub fn new(scope: OptionScope) -> Self {
+        let values = OPTIONS
+            .iter()
+            .filter(|def| scope == OptionScope::Global || def.scope == scope)
+            .map(|def| (def.name, def.default_value()))
+            .collect();
+        Self { values }
+    }
+
+    pub fn local_copy(&self) -> Self {
+        let values = OPTIONS
+            .iter()
+            .filter(|def| def.scope == OptionScope::Buffer)
+            .filter_map(|def| Some((def.name, self.values.get(def.name)?.clone())))
+            .collect();
+        Self { values }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OptionValue> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, def: &OptionDef, value: OptionValue) {
+        self.values.insert(def.name, value);
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        matches!(self.values.get(name), Some(OptionValue::Bool(true)))
+    }
+
+    pub fn get_number(&self, name: &str) -> usize {
+        match self.values.get(name) {
+            Some(OptionValue::Number(n)) => *n,
+            _ => 0,
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> String {
+        self.values
+            .get(name)
+            .map_or(String::new(), |value| value.to_string())
+    }
+
+    pub fn get_list(&self, name: &str) -> Vec<String> {
+        match self.values.get(name) {
+            Some(OptionValue::List(items)) => items.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
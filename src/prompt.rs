@@ -0,0 +1,92 @@
+use crate::{error::FileError, swap::SwapInfo};
+
+pub enum PromptAction {
+    // a swap file was found when opening the buffer with this key
+    Swap { buffer: usize, swap: SwapInfo },
+    // the file opened in this buffer is not valid UTF-8
+    View { buffer: usize },
+    // the file of this buffer changed on disk while it has changes of its own
+    Reload { buffer: usize },
+}
+
+// a question shown over the screen that waits for one of its choice keys
+pub struct Prompt {
+    pub lines: Vec<String>,
+    pub question: String,
+    pub choices: Vec<u8>,
+    pub action: PromptAction,
+}
+
+impl Prompt {
+    pub fn swap(buffer: usize, filename: &str, swap: SwapInfo) -> Self {
+        let mut lines = vec![
+            String::from("ATTENTION"),
+            format!("Found a swap file by the name \"{}\"", swap.path.display()),
+        ];
+        lines.extend(swap.describe());
+        lines.push(format!("While opening file \"{}\"", filename));
+        lines.push(String::new());
+        if swap.is_running() {
+            lines.push(String::from(
+                "Another program may be editing the same file. Quit, or continue with caution.",
+            ));
+        } else {
+            lines.push(String::from(
+                "The editor may have crashed. Recover the changes, or delete the swap file.",
+            ));
+        }
+        let (question, choices) = if swap.is_running() {
+            (
+                "[O]pen Read-Only, (E)dit anyway, (R)ecover, (Q)uit: ",
+                b"oerq".to_vec(),
+            )
+        } else {
+            (
+                "[O]pen Read-Only, (E)dit anyway, (R)ecover, (D)elete it, (Q)uit: ",
+                b"oerdq".to_vec(),
+            )
+        };
+        Self {
+            lines,
+            question: question.to_string(),
+            choices,
+            action: PromptAction::Swap { buffer, swap },
+        }
+    }
+
+    pub fn view(buffer: usize, error: &FileError) -> Self {
+        Self {
+            lines: vec![
+                error.to_string(),
+                String::from("It may be a binary file, or text in another encoding."),
+                String::from("Either view is read-only and is never written back over the file."),
+            ],
+            question: String::from("[L]ossy view, (H)ex view, (Q)uit: "),
+            choices: b"lhq".to_vec(),
+            action: PromptAction::View { buffer },
+        }
+    }
+
+    pub fn reload(buffer: usize, filename: &str, changed: bool) -> Self {
+        let mut line = format!("\"{}\" has changed on disk since it was read", filename);
+        if changed {
+            line.push_str(", and the buffer has changes too");
+        }
+        Self {
+            lines: vec![line],
+            question: String::from("File changed on disk: [L]oad, [K]eep: "),
+            // keeping is the default, loading drops the changes in the buffer
+            choices: b"kl".to_vec(),
+            action: PromptAction::Reload { buffer },
+        }
+    }
+
+    // the choice for a key, escape takes the default
+    pub fn answer(&self, c: u8) -> Option<u8> {
+        let c = match c {
+            b'\x1b' | b'\r' | b'\n' => self.choices[0],
+            c => c.to_ascii_lowercase(),
+        };
+        self.choices.contains(&c).then_some(c)
+    }
+}
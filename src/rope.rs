@@ -0,0 +1,757 @@
+use memmap2::Mmap;
+use std::{
+    ops::{Index, Range},
+    slice, str,
+    sync::Arc,
+};
+
+const MAX_LEAF: usize = 64;
+const MAX_CHILDREN: usize = 16;
+
+#[derive(Clone)]
+enum Node {
+    Leaf(Vec<String>),
+    // byte ranges of lines in a mapped file, copied into a Leaf the first time they change
+    Mapped {
+        map: Arc<Mmap>,
+        lines: Vec<Range<usize>>,
+    },
+    Branch {
+        len: usize,
+        children: Vec<Arc<Node>>,
+    },
+}
+
+fn mapped_str<'a>(map: &'a [u8], range: &Range<usize>) -> &'a str {
+    str::from_utf8(&map[range.clone()]).unwrap_or("")
+}
+
+impl Node {
+    fn branch(children: Vec<Arc<Node>>) -> Self {
+        Node::Branch {
+            len: children.iter().map(|child| child.len()).sum(),
+            children,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(lines) => lines.len(),
+            Node::Mapped { lines, .. } => lines.len(),
+            Node::Branch { len, .. } => *len,
+        }
+    }
+
+    // entries held directly, lines for a leaf and children for a branch
+    fn width(&self) -> usize {
+        match self {
+            Node::Branch { children, .. } => children.len(),
+            _ => self.len(),
+        }
+    }
+
+    fn max_width(&self) -> usize {
+        match self {
+            Node::Branch { .. } => MAX_CHILDREN,
+            _ => MAX_LEAF,
+        }
+    }
+
+    fn is_underfull(&self) -> bool {
+        self.width() < self.max_width() / 4
+    }
+
+    fn make_owned(&mut self) {
+        if let Node::Mapped { map, lines } = self {
+            let owned = lines
+                .iter()
+                .map(|range| mapped_str(map, range).to_string())
+                .collect();
+            *self = Node::Leaf(owned);
+        }
+    }
+
+    fn is_mapped(&self) -> bool {
+        match self {
+            Node::Leaf(_) => false,
+            Node::Mapped { .. } => true,
+            Node::Branch { children, .. } => children.iter().any(|child| child.is_mapped()),
+        }
+    }
+
+    // copies every mapped leaf below, leaving owned subtrees shared
+    fn own_all(&mut self) {
+        match self {
+            Node::Branch { children, .. } => {
+                for child in children.iter_mut().filter(|child| child.is_mapped()) {
+                    Arc::make_mut(child).own_all();
+                }
+            }
+            _ => self.make_owned(),
+        }
+    }
+
+    fn into_lines(mut self) -> Vec<String> {
+        self.make_owned();
+        match self {
+            Node::Leaf(lines) => lines,
+            _ => Vec::new(),
+        }
+    }
+
+    fn get(&self, mut at: usize) -> &str {
+        let mut node = self;
+        loop {
+            match node {
+                Node::Leaf(lines) => return &lines[at],
+                Node::Mapped { map, lines } => return mapped_str(map, &lines[at]),
+                Node::Branch { children, .. } => {
+                    let (i, offset) = locate(children, at);
+                    node = &children[i];
+                    at = offset;
+                }
+            }
+        }
+    }
+
+    fn get_mut(&mut self, at: usize) -> &mut String {
+        self.make_owned();
+        match self {
+            Node::Leaf(lines) => &mut lines[at],
+            Node::Branch { children, .. } => {
+                let (i, offset) = locate(children, at);
+                Arc::make_mut(&mut children[i]).get_mut(offset)
+            }
+            Node::Mapped { .. } => unreachable!("mapped leaves are made owned first"),
+        }
+    }
+
+    fn split_off(&mut self, at: usize) -> Node {
+        match self {
+            Node::Leaf(lines) => Node::Leaf(lines.split_off(at)),
+            Node::Mapped { map, lines } => Node::Mapped {
+                map: map.clone(),
+                lines: lines.split_off(at),
+            },
+            Node::Branch { len, children } => {
+                let right = children.split_off(at);
+                *len = children.iter().map(|child| child.len()).sum();
+                Node::branch(right)
+            }
+        }
+    }
+
+    // returns the right half when the node is too wide
+    fn split_if_full(&mut self) -> Option<Node> {
+        (self.width() > self.max_width()).then(|| self.split_off(self.width() / 2))
+    }
+
+    // other must be at the same height
+    fn append(&mut self, other: Node) {
+        match (&mut *self, other) {
+            (
+                Node::Branch { len, children },
+                Node::Branch {
+                    len: more,
+                    children: nodes,
+                },
+            ) => {
+                *len += more;
+                children.extend(nodes);
+            }
+            (
+                Node::Mapped { map, lines },
+                Node::Mapped {
+                    map: other,
+                    lines: more,
+                },
+            ) if Arc::ptr_eq(map, &other) => {
+                lines.extend(more);
+            }
+            (_, other) => {
+                self.make_owned();
+                if let Node::Leaf(lines) = self {
+                    lines.extend(other.into_lines());
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, at: usize, line: String) -> Option<Node> {
+        self.make_owned();
+        match self {
+            Node::Leaf(lines) => lines.insert(at, line),
+            Node::Branch { len, children } => {
+                *len += 1;
+                let (i, offset) = locate(children, at);
+                if let Some(right) = Arc::make_mut(&mut children[i]).insert(offset, line) {
+                    children.insert(i + 1, Arc::new(right));
+                }
+            }
+            Node::Mapped { .. } => unreachable!("mapped leaves are made owned first"),
+        }
+        self.split_if_full()
+    }
+
+    // adds a leaf along the right edge of the tree
+    fn push_leaf(&mut self, leaf: Arc<Node>) -> Option<Node> {
+        if let Node::Branch { len, children } = self {
+            *len += leaf.len();
+            let last = children.last_mut().expect("branches are never empty");
+            if matches!(**last, Node::Branch { .. }) {
+                if let Some(right) = Arc::make_mut(last).push_leaf(leaf) {
+                    children.push(Arc::new(right));
+                }
+            } else {
+                children.push(leaf);
+            }
+        }
+        self.split_if_full()
+    }
+
+    fn remove_range(&mut self, start: usize, end: usize) {
+        match self {
+            Node::Leaf(lines) => {
+                lines.drain(start..end);
+            }
+            Node::Mapped { lines, .. } => {
+                lines.drain(start..end);
+            }
+            Node::Branch { len, children } => {
+                let mut offset = 0;
+                children.retain_mut(|child| {
+                    let child_len = child.len();
+                    let (from, to) = (offset, offset + child_len);
+                    offset = to;
+                    if end <= from || start >= to {
+                        return true;
+                    }
+                    if start <= from && end >= to {
+                        return false;
+                    }
+                    Arc::make_mut(child).remove_range(start.max(from) - from, end.min(to) - from);
+                    true
+                });
+                let mut i = 0;
+                while i < children.len() {
+                    if children.len() > 1 && children[i].is_underfull() {
+                        merge(children, i);
+                    } else {
+                        i += 1;
+                    }
+                }
+                *len -= end - start;
+            }
+        }
+    }
+}
+
+// the child holding line at and the line's index inside it, past the end goes to the last child
+fn locate(children: &[Arc<Node>], mut at: usize) -> (usize, usize) {
+    let last = children.len() - 1;
+    for (i, child) in children.iter().enumerate() {
+        if at < child.len() || i == last {
+            return (i, at);
+        }
+        at -= child.len();
+    }
+    (last, at)
+}
+
+// joins child i with a neighbour, splitting the result again if it is too wide
+fn merge(children: &mut Vec<Arc<Node>>, i: usize) {
+    let i = if i + 1 < children.len() { i } else { i - 1 };
+    let right = Arc::unwrap_or_clone(children.remove(i + 1));
+    let mut left = Arc::unwrap_or_clone(children.remove(i));
+    left.append(right);
+    let second = left.split_if_full();
+    if left.width() > 0 {
+        children.insert(i, Arc::new(left));
+    }
+    if let Some(second) = second {
+        children.insert(i + 1, Arc::new(second));
+    }
+}
+
+// the lines of a buffer as a persistent B-tree: indexing and edits are O(log n) and
+// clone only copies the root, so old versions can be kept around cheaply
+#[derive(Clone)]
+pub struct Rope {
+    root: Arc<Node>,
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self {
+            root: Arc::new(Node::Leaf(Vec::new())),
+        }
+    }
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // true when both are the same version, without comparing the lines
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+
+    pub fn get(&self, y: usize) -> Option<&str> {
+        (y < self.len()).then(|| self.root.get(y))
+    }
+
+    pub fn get_mut(&mut self, y: usize) -> Option<&mut String> {
+        if y >= self.len() {
+            return None;
+        }
+        Some(Arc::make_mut(&mut self.root).get_mut(y))
+    }
+
+    pub fn first(&self) -> Option<&str> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&str> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub fn insert(&mut self, y: usize, line: String) {
+        assert!(y <= self.len(), "insert index {} out of range", y);
+        if let Some(right) = Arc::make_mut(&mut self.root).insert(y, line) {
+            let left = std::mem::take(self);
+            self.root = Arc::new(Node::branch(vec![left.root, Arc::new(right)]));
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        self.insert(self.len(), line);
+    }
+
+    // true while some lines are still read from a memory map
+    pub fn is_mapped(&self) -> bool {
+        self.root.is_mapped()
+    }
+
+    // copies the lines still in a memory map, needed before the mapped file is overwritten
+    pub fn make_owned(&mut self) {
+        if self.is_mapped() {
+            Arc::make_mut(&mut self.root).own_all();
+        }
+    }
+
+    // appends lines that stay in the memory map until they are edited
+    pub fn append_mapped(&mut self, map: &Arc<Mmap>, lines: &[Range<usize>]) {
+        for chunk in lines.chunks(MAX_LEAF) {
+            self.push_leaf(Node::Mapped {
+                map: map.clone(),
+                lines: chunk.to_vec(),
+            });
+        }
+    }
+
+    fn push_leaf(&mut self, leaf: Node) {
+        if self.is_empty() {
+            self.root = Arc::new(leaf);
+            return;
+        }
+        let leaf = Arc::new(leaf);
+        if !matches!(*self.root, Node::Branch { .. }) {
+            let left = std::mem::take(self);
+            self.root = Arc::new(Node::branch(vec![left.root, leaf]));
+            return;
+        }
+        if let Some(right) = Arc::make_mut(&mut self.root).push_leaf(leaf) {
+            let left = std::mem::take(self);
+            self.root = Arc::new(Node::branch(vec![left.root, Arc::new(right)]));
+        }
+    }
+
+    pub fn remove_range(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.len());
+        if range.start >= end {
+            return;
+        }
+        Arc::make_mut(&mut self.root).remove_range(range.start, end);
+        loop {
+            match &*self.root {
+                Node::Branch { children, .. } if children.len() == 1 => {
+                    self.root = children[0].clone();
+                }
+                Node::Branch { children, .. } if children.is_empty() => {
+                    *self = Self::default();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0)
+    }
+
+    // starts at line y without walking the lines before it
+    pub fn iter_from(&self, y: usize) -> Iter<'_> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            leaf: LeafIter::Owned([].iter()),
+            remaining: self.len().saturating_sub(y),
+        };
+        if iter.remaining == 0 {
+            return iter;
+        }
+        let mut node = &*self.root;
+        let mut at = y;
+        loop {
+            match node {
+                Node::Leaf(lines) => {
+                    iter.leaf = LeafIter::Owned(lines[at..].iter());
+                    return iter;
+                }
+                Node::Mapped { map, lines } => {
+                    iter.leaf = LeafIter::Mapped(map, lines[at..].iter());
+                    return iter;
+                }
+                Node::Branch { children, .. } => {
+                    let (i, offset) = locate(children, at);
+                    iter.stack.push(children[i + 1..].iter());
+                    node = &children[i];
+                    at = offset;
+                }
+            }
+        }
+    }
+}
+
+impl Index<usize> for Rope {
+    type Output = str;
+
+    fn index(&self, y: usize) -> &str {
+        self.get(y)
+            .unwrap_or_else(|| panic!("line {} out of range for {} lines", y, self.len()))
+    }
+}
+
+impl FromIterator<String> for Rope {
+    // fills leaves in order and builds the levels above them, no rebalancing needed
+    fn from_iter<I: IntoIterator<Item = String>>(lines: I) -> Self {
+        let mut nodes = Vec::new();
+        let mut leaf = Vec::with_capacity(MAX_LEAF);
+        for line in lines {
+            leaf.push(line);
+            if leaf.len() == MAX_LEAF {
+                nodes.push(Arc::new(Node::Leaf(std::mem::take(&mut leaf))));
+            }
+        }
+        if !leaf.is_empty() || nodes.is_empty() {
+            nodes.push(Arc::new(Node::Leaf(leaf)));
+        }
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(MAX_CHILDREN)
+                .map(|chunk| Arc::new(Node::branch(chunk.to_vec())))
+                .collect();
+        }
+        Self {
+            root: nodes.remove(0),
+        }
+    }
+}
+
+impl From<Vec<String>> for Rope {
+    fn from(lines: Vec<String>) -> Self {
+        lines.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a Rope {
+    type Item = &'a str;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+enum LeafIter<'a> {
+    Owned(slice::Iter<'a, String>),
+    Mapped(&'a [u8], slice::Iter<'a, Range<usize>>),
+}
+
+impl<'a> Iterator for LeafIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            LeafIter::Owned(lines) => lines.next().map(|line| line.as_str()),
+            LeafIter::Mapped(map, lines) => lines.next().map(|range| mapped_str(map, range)),
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    stack: Vec<slice::Iter<'a, Arc<Node>>>,
+    leaf: LeafIter<'a>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            if let Some(line) = self.leaf.next() {
+                self.remaining -= 1;
+                return Some(line);
+            }
+            let node = loop {
+                let top = self.stack.last_mut()?;
+                match top.next() {
+                    Some(node) => break node,
+                    None => {
+                        self.stack.pop();
+                    }
+                }
+            };
+            let mut node = &**node;
+            loop {
+                match node {
+                    Node::Leaf(lines) => {
+                        self.leaf = LeafIter::Owned(lines.iter());
+                        break;
+                    }
+                    Node::Mapped { map, lines } => {
+                        self.leaf = LeafIter::Mapped(map, lines.iter());
+                        break;
+                    }
+                    Node::Branch { children, .. } => {
+                        let mut children = children.iter();
+                        let Some(first) = children.next() else { break };
+                        self.stack.push(children);
+                        node = first;
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    // the number of lines, checking that lengths add up, no node is too wide and every leaf
+    // is at the same depth
+    fn check(node: &Node, depth: usize, leaves: &mut Option<usize>) -> usize {
+        assert!(node.width() <= node.max_width());
+        match node {
+            Node::Branch { len, children } => {
+                assert!(!children.is_empty());
+                let sum = children
+                    .iter()
+                    .map(|child| check(child, depth + 1, leaves))
+                    .sum();
+                assert_eq!(*len, sum);
+                sum
+            }
+            _ => {
+                assert_eq!(*leaves.get_or_insert(depth), depth);
+                node.len()
+            }
+        }
+    }
+
+    fn assert_matches(rope: &Rope, model: &[String]) {
+        check(&rope.root, 0, &mut None);
+        assert_eq!(rope.len(), model.len());
+        assert!(rope.iter().eq(model.iter().map(String::as_str)));
+        for (y, line) in model.iter().enumerate() {
+            assert_eq!(&rope[y], line);
+        }
+        assert_eq!(rope.get(model.len()), None);
+    }
+
+    fn lines(range: Range<usize>) -> Vec<String> {
+        range.map(|i| format!("line {}", i)).collect()
+    }
+
+    // xorshift, enough to mix the operations without a dependency
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n.max(1) as u64) as usize
+        }
+    }
+
+    #[test]
+    fn insert_splits() {
+        let mut rope = Rope::new();
+        let mut model = Vec::new();
+        for i in 0..5000 {
+            // front, back and middle inserts all have to split nodes
+            let at = match i % 3 {
+                0 => 0,
+                1 => model.len(),
+                _ => model.len() / 2,
+            };
+            rope.insert(at, i.to_string());
+            model.insert(at, i.to_string());
+        }
+        assert!(matches!(*rope.root, Node::Branch { .. }));
+        assert_matches(&rope, &model);
+    }
+
+    #[test]
+    fn remove_across_leaves() {
+        let mut model = lines(0..10000);
+        let mut rope = Rope::from(model.clone());
+        for (start, end) in [(10, 3000), (0, 1), (500, 520), (4000, 7000), (100, 10000)] {
+            let end = end.min(model.len());
+            rope.remove_range(start..end);
+            model.drain(start..end);
+            assert_matches(&rope, &model);
+        }
+        rope.remove_range(0..rope.len());
+        assert!(rope.is_empty());
+        rope.push(String::from("again"));
+        assert_matches(&rope, &[String::from("again")]);
+    }
+
+    #[test]
+    fn iter_from_every_line() {
+        let model = lines(0..3000);
+        let rope = Rope::from(model.clone());
+        for y in (0..=3000)
+            .step_by(7)
+            .chain([63, 64, 65, 1023, 1024, 2999, 3000, 4000])
+        {
+            let rest: Vec<&str> = rope.iter_from(y).collect();
+            let expected = model.get(y..).unwrap_or_default();
+            assert_eq!(rest, expected);
+            assert_eq!(rope.iter_from(y).len(), expected.len());
+        }
+    }
+
+    #[test]
+    fn snapshots_are_isolated() {
+        let model = lines(0..2000);
+        let mut rope = Rope::from(model.clone());
+        let snapshot = rope.clone();
+        assert!(rope.ptr_eq(&snapshot));
+        rope.get_mut(1500).unwrap().push_str(" changed");
+        rope.insert(10, String::from("new"));
+        rope.remove_range(100..900);
+        assert!(!rope.ptr_eq(&snapshot));
+        assert_matches(&snapshot, &model);
+        assert_eq!(&rope[10], "new");
+        assert_eq!(&rope[1500 + 1 - 800], "line 1500 changed");
+    }
+
+    #[test]
+    fn random_edits_match_a_vec() {
+        for seed in 1..40u64 {
+            let mut random = Random(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut rope = Rope::new();
+            let mut model: Vec<String> = Vec::new();
+            let mut snapshots = Vec::new();
+            for step in 0..400 {
+                match random.below(6) {
+                    0 | 1 => {
+                        let at = random.below(model.len() + 1);
+                        let count = 1 + random.below(100);
+                        for i in 0..count {
+                            let line = format!("{} {} {}", seed, step, i);
+                            rope.insert(at + i, line.clone());
+                            model.insert(at + i, line);
+                        }
+                    }
+                    2 => {
+                        let start = random.below(model.len() + 1);
+                        let end = start + random.below(model.len() - start + 1);
+                        rope.remove_range(start..end);
+                        model.drain(start..end);
+                    }
+                    3 if !model.is_empty() => {
+                        let y = random.below(model.len());
+                        rope.get_mut(y).unwrap().push('!');
+                        model[y].push('!');
+                    }
+                    4 => snapshots.push((rope.clone(), model.clone())),
+                    _ => {
+                        let y = random.below(model.len() + 1);
+                        assert!(rope.iter_from(y).eq(model[y..].iter().map(String::as_str)));
+                    }
+                }
+            }
+            assert_matches(&rope, &model);
+            for (rope, model) in &snapshots {
+                assert_matches(rope, model);
+            }
+        }
+    }
+
+    #[test]
+    fn mapped_leaves() {
+        let path = std::env::temp_dir().join(format!("rusty-vim-{}-rope-mapped", process::id()));
+        let model = lines(0..1000);
+        fs::write(&path, model.join("\n")).unwrap();
+        let map = Arc::new(unsafe { Mmap::map(&fs::File::open(&path).unwrap()).unwrap() });
+        fs::remove_file(&path).unwrap();
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for line in &model {
+            ranges.push(start..start + line.len());
+            start += line.len() + 1;
+        }
+        // pushed in uneven pieces, the way the loader hands them over
+        let mut rope = Rope::new();
+        let mut pushed = Vec::new();
+        for piece in ranges.chunks(150) {
+            rope.append_mapped(&map, piece);
+            pushed.extend(
+                piece
+                    .iter()
+                    .map(|range| mapped_str(&map, range).to_string()),
+            );
+            assert_matches(&rope, &pushed);
+        }
+        assert!(rope.is_mapped());
+        let snapshot = rope.clone();
+
+        let mut model = model;
+        rope.get_mut(500).unwrap().push_str(" edited");
+        model[500].push_str(" edited");
+        rope.insert(0, String::from("first"));
+        model.insert(0, String::from("first"));
+        rope.remove_range(200..700);
+        model.drain(200..700);
+        assert_matches(&rope, &model);
+        assert!(rope.is_mapped());
+
+        rope.make_owned();
+        assert!(!rope.is_mapped());
+        assert_matches(&rope, &model);
+        assert!(snapshot.is_mapped());
+        assert_matches(&snapshot, &lines(0..1000));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::theme::Group;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Group,
+    pub bg: Group,
+}
+
+impl Cell {
+    pub fn new(ch: char, fg: Group, bg: Group) -> Self {
+        Self { ch, fg, bg }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::new(' ', Group::Normal, Group::Normal)
+    }
+}
+
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    // control characters would move the real cursor, so they are drawn as blanks
+    pub fn set(&mut self, x: usize, y: usize, mut cell: Cell) {
+        if cell.ch.is_control() {
+            cell.ch = ' ';
+        }
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    pub fn fill_row(&mut self, y: usize, from: usize, fg: Group, bg: Group) {
+        self.fill(y, from, self.width, fg, bg);
+    }
+
+    pub fn fill(&mut self, y: usize, from: usize, to: usize, fg: Group, bg: Group) {
+        for x in from..to.min(self.width) {
+            self.set(x, y, Cell::new(' ', fg, bg));
+        }
+    }
+
+    // clipped at the right edge, returns the column after the text
+    pub fn put_str(&mut self, x: usize, y: usize, text: &str, fg: Group, bg: Group) -> usize {
+        let mut col = x;
+        for ch in text.chars() {
+            self.set(col, y, Cell::new(ch, fg, bg));
+            col += 1;
+        }
+        col
+    }
+}
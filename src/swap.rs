@@ -0,0 +1,394 @@
+use crate::{config, rope::Rope, terminal::Position};
+use std::{
+    ffi::CStr,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, BufWriter, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{self, Path, PathBuf},
+    process,
+    thread::{self, JoinHandle},
+};
+
+const MAGIC: &str = "rusty-vim swap";
+// the last letter of the extension, tried in order when a swap file is already in use
+const SUFFIXES: &[u8] = b"ponmlkjihgfedcba";
+
+// where swap files go, the `directory` option or the state directory
+pub fn directory(option: &str) -> Option<PathBuf> {
+    if !option.is_empty() {
+        return Some(PathBuf::from(option));
+    }
+    Some(config::state_dir()?.join("swap"))
+}
+
+// every file gets its own names in the one directory, with its full path spelled out
+fn candidates(dir: &Path, filename: &str) -> Vec<PathBuf> {
+    let full = path::absolute(filename).unwrap_or_else(|_| PathBuf::from(filename));
+    let name = full.to_string_lossy().replace('/', "%");
+    SUFFIXES
+        .iter()
+        .map(|&c| dir.join(format!("{}.sw{}", name, c as char)))
+        .collect()
+}
+
+pub struct SwapInfo {
+    pub path: PathBuf,
+    pub file: String,
+    pub pid: u32,
+    pub host: String,
+    pub modified: bool,
+    pub cursor: Position,
+}
+
+impl SwapInfo {
+    fn read(path: &Path) -> io::Result<(Self, Option<String>)> {
+        let contents = fs::read_to_string(path)?;
+        let (header, text) = contents.split_once("\n\n").unwrap_or((&contents, ""));
+        let mut lines = header.lines();
+        if lines.next() != Some(MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a swap file",
+            ));
+        }
+        let mut info = Self {
+            path: path.to_path_buf(),
+            file: String::new(),
+            pid: 0,
+            host: String::new(),
+            modified: false,
+            cursor: Position::new(),
+        };
+        for (key, value) in lines.filter_map(|line| line.split_once('=')) {
+            match key {
+                "file" => info.file = value.to_string(),
+                "pid" => info.pid = value.parse().unwrap_or(0),
+                "host" => info.host = value.to_string(),
+                "modified" => info.modified = value == "1",
+                "cursor" => {
+                    if let Some((y, x)) = value.split_once(',') {
+                        info.cursor.y = y.parse().unwrap_or(0);
+                        info.cursor.x = x.parse().unwrap_or(0);
+                    }
+                }
+                _ => (),
+            }
+        }
+        let text = info.modified.then(|| text.to_string());
+        Ok((info, text))
+    }
+
+    // whether the editor that wrote it is still editing the file
+    pub fn is_running(&self) -> bool {
+        self.host == hostname() && self.pid != 0 && process_exists(self.pid)
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let running = if self.is_running() {
+            " (STILL RUNNING)"
+        } else {
+            ""
+        };
+        vec![
+            format!("          file name: {}", self.file),
+            format!(
+                "           modified: {}",
+                if self.modified { "YES" } else { "no" }
+            ),
+            format!("          host name: {}", self.host),
+            format!("         process ID: {}{}", self.pid, running),
+        ]
+    }
+
+    // the text and cursor saved in the swap file, None when it holds no changes
+    pub fn recover(&self) -> io::Result<Option<(Rope, Position)>> {
+        let (_, text) = Self::read(&self.path)?;
+        Ok(text.map(|text| {
+            let mut lines: Vec<String> = text.split('\n').map(|line| line.to_string()).collect();
+            lines.pop();
+            (Rope::from(lines), self.cursor)
+        }))
+    }
+}
+
+// swap files left for filename, other than the one in use by this editor
+pub fn find(dir: &Path, filename: &str, own: Option<&Path>) -> Vec<SwapInfo> {
+    candidates(dir, filename)
+        .into_iter()
+        .filter(|path| Some(path.as_path()) != own)
+        .filter_map(|path| SwapInfo::read(&path).ok())
+        .map(|(info, _)| info)
+        .collect()
+}
+
+// the listing printed by `rusty-vim -r`
+pub fn recoverable_files() -> String {
+    let Some(dir) = directory("") else {
+        return String::from("No swap directory\n");
+    };
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| {
+                    path.extension().is_some_and(|ext| {
+                        ext.len() == 3 && ext.to_string_lossy().starts_with("sw")
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    let mut out = format!("Swap files found in {}:\n", dir.display());
+    let mut count = 0;
+    for path in paths {
+        let Ok((info, _)) = SwapInfo::read(&path) else {
+            continue;
+        };
+        count += 1;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        out.push_str(&format!("{}.    {}\n", count, name));
+        for line in info.describe() {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    if count == 0 {
+        out.push_str("   -- none --\n");
+    }
+    out
+}
+
+// the swap file of an open buffer, written in the background and removed when it is dropped
+pub struct SwapFile {
+    path: PathBuf,
+    filename: String,
+    // the permissions of the file, the swap file is readable by no one who cannot read it
+    mode: u32,
+    // what was last written, to skip writes when nothing changed
+    last: Option<(Rope, bool, Position)>,
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl SwapFile {
+    // takes the first free name, or path when given
+    pub fn create(dir: &Path, filename: &str, path: Option<PathBuf>) -> io::Result<Self> {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        let path = match path {
+            Some(path) => path,
+            None => candidates(dir, filename)
+                .into_iter()
+                .find(|path| !path.exists())
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::AlreadyExists, "too many swap files")
+                })?,
+        };
+        let full = path::absolute(filename).unwrap_or_else(|_| PathBuf::from(filename));
+        let mode = fs::metadata(filename).map_or(0o600, |metadata| {
+            (metadata.permissions().mode() & 0o777) | 0o600
+        });
+        let mut swap = Self {
+            path,
+            filename: full.to_string_lossy().into_owned(),
+            mode,
+            last: None,
+            writer: None,
+        };
+        write_swap(
+            &swap.path,
+            swap.mode,
+            &swap.header(false, Position::new()),
+            None,
+        )?;
+        swap.last = Some((Rope::new(), false, Position::new()));
+        Ok(swap)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn header(&self, modified: bool, cursor: Position) -> String {
+        format!(
+            "{}\nfile={}\npid={}\nhost={}\nmodified={}\ncursor={},{}\n\n",
+            MAGIC,
+            self.filename,
+            process::id(),
+            hostname(),
+            if modified { 1 } else { 0 },
+            cursor.y,
+            cursor.x
+        )
+    }
+
+    // rows is a cheap snapshot, the text is only written while the buffer has changes
+    pub fn sync(&mut self, rows: &Rope, modified: bool, cursor: Position) {
+        if let Some((last, was_modified, at)) = &self.last
+            && *was_modified == modified
+            && *at == cursor
+            && (!modified || last.ptr_eq(rows))
+        {
+            return;
+        }
+        self.wait();
+        let header = self.header(modified, cursor);
+        let path = self.path.clone();
+        let mode = self.mode;
+        let text = modified.then(|| rows.clone());
+        self.last = Some((rows.clone(), modified, cursor));
+        self.writer = Some(thread::spawn(move || {
+            write_swap(&path, mode, &header, text.as_ref())
+        }));
+    }
+
+    // blocks until the last write is done
+    pub fn wait(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Drop for SwapFile {
+    // kept after a panic so the changes can still be recovered
+    fn drop(&mut self) {
+        self.wait();
+        if !thread::panicking() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn write_swap(path: &Path, mode: u32, header: &str, text: Option<&Rope>) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    // a left over temporary file would keep its own permissions
+    let _ = fs::remove_file(&temp);
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(&temp)?;
+    let mut file = BufWriter::new(file);
+    file.write_all(header.as_bytes())?;
+    for line in text.into_iter().flat_map(|rows| rows.iter()) {
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")?;
+    }
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return String::new();
+    }
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn process_exists(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    let exists = unsafe { libc::kill(pid, 0) } == 0;
+    exists || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a swap directory and a file to edit of their own for each test, removed when dropped
+    struct Temp {
+        dir: PathBuf,
+        file: PathBuf,
+    }
+
+    impl Temp {
+        fn new(name: &str) -> Self {
+            let base = std::env::temp_dir().join(format!("rusty-vim-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&base);
+            fs::create_dir(&base).unwrap();
+            Self {
+                dir: base.join("swap"),
+                file: base.join("file.txt"),
+            }
+        }
+
+        fn name(&self) -> &str {
+            self.file.to_str().unwrap()
+        }
+    }
+
+    impl Drop for Temp {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.dir.parent().unwrap());
+        }
+    }
+
+    fn lines(rows: &Rope) -> Vec<String> {
+        rows.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn sync_and_recover() {
+        let temp = Temp::new("swap-recover");
+        fs::write(&temp.file, "one\ntwo\n").unwrap();
+        let mut swap = SwapFile::create(&temp.dir, temp.name(), None).unwrap();
+        let rows = Rope::from(vec![
+            String::from("one"),
+            String::new(),
+            String::from("three"),
+        ]);
+        let mut cursor = Position::new();
+        cursor.y = 2;
+        cursor.x = 4;
+        swap.sync(&rows, true, cursor);
+        swap.wait();
+        let found = find(&temp.dir, temp.name(), None);
+        assert_eq!(found.len(), 1);
+        let info = &found[0];
+        assert!(info.modified && info.is_running());
+        assert_eq!(info.cursor, cursor);
+        let (recovered, at) = info.recover().unwrap().unwrap();
+        assert_eq!(lines(&recovered), lines(&rows));
+        assert_eq!(at, cursor);
+        // once written the text is left out
+        swap.sync(&rows, false, cursor);
+        swap.wait();
+        let info = &find(&temp.dir, temp.name(), None)[0];
+        assert!(!info.modified);
+        assert!(info.recover().unwrap().is_none());
+        let path = swap.path().to_path_buf();
+        drop(swap);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn permissions() {
+        let temp = Temp::new("swap-mode");
+        let swap = SwapFile::create(&temp.dir, temp.name(), None).unwrap();
+        assert_eq!(mode(&temp.dir), 0o700);
+        assert_eq!(mode(swap.path()), 0o600);
+        drop(swap);
+        fs::write(&temp.file, "text\n").unwrap();
+        fs::set_permissions(&temp.file, fs::Permissions::from_mode(0o640)).unwrap();
+        let mut swap = SwapFile::create(&temp.dir, temp.name(), None).unwrap();
+        assert_eq!(mode(swap.path()), 0o640);
+        swap.sync(
+            &Rope::from(vec![String::from("text")]),
+            true,
+            Position::new(),
+        );
+        swap.wait();
+        assert_eq!(mode(swap.path()), 0o640);
+    }
+}
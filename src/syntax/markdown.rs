@@ -0,0 +1,134 @@
+use super::{Grammar, LineState, Scanner, Token, TokenKind};
+
+pub struct Markdown;
+
+impl Grammar for Markdown {
+    fn highlight_line(&self, line: &str, state: &LineState) -> (Vec<Token>, LineState) {
+        let mut s = Scanner::new(line);
+        match state {
+            LineState::CodeBlock(fence) => {
+                s.rest_as(TokenKind::Code);
+                if line.trim() == fence {
+                    return (s.tokens, LineState::Normal);
+                }
+                return (s.tokens, state.clone());
+            }
+            LineState::HtmlComment => {
+                if !html_comment(&mut s, 0) {
+                    return (s.tokens, LineState::HtmlComment);
+                }
+            }
+            _ => {
+                if let Some(state) = block(&mut s, line) {
+                    return (s.tokens, state);
+                }
+            }
+        }
+        while !s.done() {
+            if s.starts_with("<!--") {
+                let start = s.pos;
+                s.pos += 4;
+                if !html_comment(&mut s, start) {
+                    return (s.tokens, LineState::HtmlComment);
+                }
+                continue;
+            }
+            inline(&mut s);
+        }
+        (s.tokens, LineState::Normal)
+    }
+}
+
+// highlights line level markup, returns the next state when the whole line was consumed
+fn block(s: &mut Scanner, line: &str) -> Option<LineState> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        let marker = trimmed.as_bytes()[0] as char;
+        let fence: String = trimmed.chars().take_while(|&c| c == marker).collect();
+        s.rest_as(TokenKind::Code);
+        return Some(LineState::CodeBlock(fence));
+    }
+    if line.starts_with('#') {
+        s.rest_as(TokenKind::Heading);
+        return Some(LineState::Normal);
+    }
+    if line.starts_with("    ") || line.starts_with('\t') {
+        s.rest_as(TokenKind::Code);
+        return Some(LineState::Normal);
+    }
+    s.eat_while(|c| c == b' ');
+    let start = s.pos;
+    if s.peek() == Some(b'>') {
+        s.rest_as(TokenKind::Comment);
+        return Some(LineState::Normal);
+    }
+    if matches!(s.peek(), Some(b'-' | b'*' | b'+')) && s.peek_at(1) == Some(b' ') {
+        s.pos += 1;
+        s.push(start, TokenKind::Keyword);
+        return None;
+    }
+    s.eat_while(|c| c.is_ascii_digit());
+    if s.pos > start && matches!(s.peek(), Some(b'.' | b')')) {
+        s.pos += 1;
+        s.push(start, TokenKind::Keyword);
+    } else {
+        s.pos = start;
+    }
+    None
+}
+
+fn inline(s: &mut Scanner) {
+    let start = s.pos;
+    match s.peek().unwrap() {
+        b'`' => {
+            s.pos += 1;
+            s.eat_while(|c| c != b'`');
+            s.pos = (s.pos + 1).min(s.bytes.len());
+            s.push(start, TokenKind::Code);
+        }
+        marker @ (b'*' | b'_') if !s.prev_is_word() => {
+            let double = s.peek_at(1) == Some(marker);
+            let width = if double { 2 } else { 1 };
+            let closing = &s.bytes[s.pos + width..];
+            let end = closing
+                .windows(width)
+                .position(|w| w.iter().all(|&c| c == marker))
+                .filter(|&i| i > 0);
+            match end {
+                Some(i) => {
+                    s.pos += width + i + width;
+                    s.push(start, TokenKind::Emphasis);
+                }
+                None => s.pos += width,
+            }
+        }
+        b'[' => {
+            let close = s.bytes[s.pos..].iter().position(|&c| c == b']');
+            match close {
+                Some(i) if s.bytes.get(s.pos + i + 1) == Some(&b'(') => {
+                    s.pos += i + 1;
+                    s.push(start, TokenKind::Link);
+                    let url_start = s.pos;
+                    s.eat_while(|c| c != b')');
+                    s.pos = (s.pos + 1).min(s.bytes.len());
+                    s.push(url_start, TokenKind::String);
+                }
+                _ => s.pos += 1,
+            }
+        }
+        _ => s.pos += 1,
+    }
+}
+
+fn html_comment(s: &mut Scanner, start: usize) -> bool {
+    while !s.done() {
+        if s.starts_with("-->") {
+            s.pos += 3;
+            s.push(start, TokenKind::Comment);
+            return true;
+        }
+        s.pos += 1;
+    }
+    s.push(start, TokenKind::Comment);
+    false
+}
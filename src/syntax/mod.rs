@@ -0,0 +1,263 @@
+pub mod markdown;
+pub mod rust;
+pub mod shell;
+pub mod toml;
+
+use crate::rope::Rope;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Function,
+    Macro,
+    Attribute,
+    String,
+    Escape,
+    Number,
+    Constant,
+    Comment,
+    Variable,
+    Key,
+    Section,
+    Heading,
+    Emphasis,
+    Link,
+    Code,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LineState {
+    #[default]
+    Normal,
+    BlockComment(usize),
+    String(u8),
+    RawString(usize),
+    MultiString(u8),
+    CodeBlock(String),
+    HtmlComment,
+    HereDoc(String, bool),
+}
+
+pub trait Grammar {
+    fn highlight_line(&self, line: &str, state: &LineState) -> (Vec<Token>, LineState);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Rust,
+    Toml,
+    Markdown,
+    Shell,
+}
+
+impl FileType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileType::Rust => "rust",
+            FileType::Toml => "toml",
+            FileType::Markdown => "markdown",
+            FileType::Shell => "sh",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rust" => Some(FileType::Rust),
+            "toml" => Some(FileType::Toml),
+            "markdown" => Some(FileType::Markdown),
+            "sh" | "bash" | "zsh" => Some(FileType::Shell),
+            _ => None,
+        }
+    }
+
+    fn grammar(&self) -> &'static dyn Grammar {
+        match self {
+            FileType::Rust => &rust::Rust,
+            FileType::Toml => &toml::Toml,
+            FileType::Markdown => &markdown::Markdown,
+            FileType::Shell => &shell::Shell,
+        }
+    }
+
+    pub fn detect(filename: Option<&str>, first_line: Option<&str>) -> Option<Self> {
+        let path = Path::new(filename.unwrap_or_default());
+        let name = path.file_name().and_then(|name| name.to_str());
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match (name, extension) {
+            (_, Some("rs")) => return Some(FileType::Rust),
+            (Some("Cargo.lock"), _) | (_, Some("toml")) => return Some(FileType::Toml),
+            (_, Some("md" | "markdown")) => return Some(FileType::Markdown),
+            (Some(".bashrc" | ".bash_profile" | ".profile" | ".zshrc"), _)
+            | (_, Some("sh" | "bash" | "zsh")) => return Some(FileType::Shell),
+            _ => (),
+        }
+        let shebang = first_line?.strip_prefix("#!")?;
+        let mut words = shebang.split_whitespace();
+        let mut interpreter = words.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            interpreter = words.next()?;
+        }
+        match interpreter {
+            "sh" | "bash" | "zsh" | "dash" | "ksh" => Some(FileType::Shell),
+            _ => None,
+        }
+    }
+}
+
+struct HighlightedLine {
+    tokens: Vec<Token>,
+    end_state: LineState,
+}
+
+#[derive(Default)]
+pub struct Highlighter {
+    filetype: Option<FileType>,
+    lines: Vec<HighlightedLine>,
+}
+
+impl Highlighter {
+    pub fn new(filetype: Option<FileType>) -> Self {
+        Self {
+            filetype,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn invalidate(&mut self, from: usize) {
+        self.lines.truncate(from);
+    }
+
+    pub fn update(&mut self, rows: &Rope, upto: usize) {
+        let grammar = match self.filetype {
+            Some(filetype) => filetype.grammar(),
+            None => return,
+        };
+        for line in rows
+            .iter_from(self.lines.len())
+            .take(upto.saturating_sub(self.lines.len()))
+        {
+            let state = self
+                .lines
+                .last()
+                .map_or(LineState::Normal, |line| line.end_state.clone());
+            let (tokens, end_state) = grammar.highlight_line(line, &state);
+            self.lines.push(HighlightedLine { tokens, end_state });
+        }
+    }
+
+    pub fn tokens(&self, y: usize) -> &[Token] {
+        self.lines.get(y).map_or(&[], |line| &line.tokens)
+    }
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(line: &'a str) -> Self {
+        Self {
+            bytes: line.as_bytes(),
+            pos: 0,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<u8> {
+        self.bytes.get(self.pos + n).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.bytes[self.pos..].starts_with(s.as_bytes())
+    }
+
+    fn eat_while(&mut self, f: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+    }
+
+    fn push(&mut self, start: usize, kind: TokenKind) {
+        if self.pos > start {
+            self.tokens.push(Token {
+                start,
+                end: self.pos,
+                kind,
+            });
+        }
+    }
+
+    fn rest_as(&mut self, kind: TokenKind) {
+        let start = self.pos;
+        self.pos = self.bytes.len();
+        self.push(start, kind);
+    }
+
+    fn prev_is_word(&self) -> bool {
+        self.pos > 0 && is_word(self.bytes[self.pos - 1])
+    }
+
+    fn word(&mut self) -> &'a str {
+        let start = self.pos;
+        self.eat_while(is_word);
+        std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or_default()
+    }
+
+    // scans up to and including the closing quote, false when the line ends first
+    fn string_body(&mut self, start: usize, quote: u8, escapes: bool) -> bool {
+        let mut segment_start = start;
+        while let Some(c) = self.peek() {
+            if escapes && c == b'\\' {
+                self.push(segment_start, TokenKind::String);
+                let escape_start = self.pos;
+                self.pos = (self.pos + 2).min(self.bytes.len());
+                self.push(escape_start, TokenKind::Escape);
+                segment_start = self.pos;
+                continue;
+            }
+            self.pos += 1;
+            if c == quote {
+                self.push(segment_start, TokenKind::String);
+                return true;
+            }
+        }
+        self.push(segment_start, TokenKind::String);
+        false
+    }
+
+    fn number(&mut self) {
+        let start = self.pos;
+        if self.starts_with("0x") || self.starts_with("0o") || self.starts_with("0b") {
+            self.pos += 2;
+        }
+        self.eat_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+        if self.peek() == Some(b'.') && self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            self.eat_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+        }
+        self.push(start, TokenKind::Number);
+    }
+}
+
+fn is_word(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
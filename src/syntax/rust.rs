@@ -0,0 +1,177 @@
+use super::{Grammar, LineState, Scanner, Token, TokenKind};
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+
+const TYPES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+    "i128", "isize", "f32", "f64",
+];
+
+pub struct Rust;
+
+impl Grammar for Rust {
+    fn highlight_line(&self, line: &str, state: &LineState) -> (Vec<Token>, LineState) {
+        let mut s = Scanner::new(line);
+        let mut state = state.clone();
+        while !s.done() {
+            state = match state {
+                LineState::BlockComment(depth) => block_comment(&mut s, depth),
+                LineState::String(_) if s.string_body(0, b'"', true) => LineState::Normal,
+                LineState::String(quote) => LineState::String(quote),
+                LineState::RawString(hashes) => raw_string(&mut s, 0, hashes),
+                _ => normal(&mut s),
+            };
+        }
+        (s.tokens, state)
+    }
+}
+
+fn normal(s: &mut Scanner) -> LineState {
+    let start = s.pos;
+    let c = s.peek().unwrap();
+    match c {
+        b'/' if s.starts_with("//") => s.rest_as(TokenKind::Comment),
+        b'/' if s.starts_with("/*") => {
+            s.pos += 2;
+            return block_comment_from(s, start, 1);
+        }
+        b'"' => {
+            s.pos += 1;
+            if !s.string_body(start, b'"', true) {
+                return LineState::String(b'"');
+            }
+        }
+        b'r' | b'b' if raw_string_start(s).is_some() => {
+            let hashes = raw_string_start(s).unwrap();
+            s.pos += hashes + if c == b'b' { 3 } else { 2 };
+            return raw_string(s, start, hashes);
+        }
+        b'b' if matches!(s.peek_at(1), Some(b'"' | b'\'')) => {
+            let quote = s.peek_at(1).unwrap();
+            s.pos += 2;
+            if !s.string_body(start, quote, true) && quote == b'"' {
+                return LineState::String(b'"');
+            }
+        }
+        b'\'' => char_or_lifetime(s),
+        b'#' if matches!(s.peek_at(1), Some(b'[' | b'!')) => {
+            let end = s.bytes[s.pos..]
+                .iter()
+                .position(|&c| c == b']')
+                .map_or(s.bytes.len(), |i| s.pos + i + 1);
+            s.pos = end;
+            s.push(start, TokenKind::Attribute);
+        }
+        c if c.is_ascii_digit() => s.number(),
+        c if super::is_word(c) => identifier(s),
+        _ => s.pos += 1,
+    }
+    LineState::Normal
+}
+
+fn identifier(s: &mut Scanner) {
+    let start = s.pos;
+    let word = s.word();
+    let kind = if KEYWORDS.contains(&word) {
+        Some(TokenKind::Keyword)
+    } else if word == "true" || word == "false" {
+        Some(TokenKind::Constant)
+    } else if s.peek() == Some(b'!') && s.peek_at(1) != Some(b'=') {
+        s.pos += 1;
+        Some(TokenKind::Macro)
+    } else if TYPES.contains(&word) || word.starts_with(|c: char| c.is_ascii_uppercase()) {
+        if word.len() > 1 && word.bytes().all(|c| !c.is_ascii_lowercase()) {
+            Some(TokenKind::Constant)
+        } else {
+            Some(TokenKind::Type)
+        }
+    } else if s.peek() == Some(b'(') {
+        Some(TokenKind::Function)
+    } else {
+        None
+    };
+    if let Some(kind) = kind {
+        s.push(start, kind);
+    }
+}
+
+fn char_or_lifetime(s: &mut Scanner) {
+    let start = s.pos;
+    s.pos += 1;
+    let is_char = match s.peek() {
+        Some(b'\\') => true,
+        Some(_) => {
+            let len = std::str::from_utf8(&s.bytes[s.pos..])
+                .ok()
+                .and_then(|rest| rest.chars().next())
+                .map_or(1, |c| c.len_utf8());
+            s.peek_at(len) == Some(b'\'')
+        }
+        None => false,
+    };
+    if is_char {
+        s.string_body(start, b'\'', true);
+    } else {
+        s.eat_while(super::is_word);
+        s.push(start, TokenKind::Type);
+    }
+}
+
+fn raw_string_start(s: &Scanner) -> Option<usize> {
+    let offset = match s.peek()? {
+        b'b' if s.peek_at(1) == Some(b'r') => 2,
+        b'r' if !s.prev_is_word() => 1,
+        _ => return None,
+    };
+    let hashes = s.bytes[s.pos + offset..]
+        .iter()
+        .take_while(|&&c| c == b'#')
+        .count();
+    match s.peek_at(offset + hashes) {
+        Some(b'"') => Some(hashes),
+        _ => None,
+    }
+}
+
+fn raw_string(s: &mut Scanner, start: usize, hashes: usize) -> LineState {
+    let closing = format!("\"{}", "#".repeat(hashes));
+    while !s.done() {
+        if s.starts_with(&closing) {
+            s.pos += closing.len();
+            s.push(start, TokenKind::String);
+            return LineState::Normal;
+        }
+        s.pos += 1;
+    }
+    s.push(start, TokenKind::String);
+    LineState::RawString(hashes)
+}
+
+fn block_comment(s: &mut Scanner, depth: usize) -> LineState {
+    block_comment_from(s, s.pos, depth)
+}
+
+fn block_comment_from(s: &mut Scanner, start: usize, mut depth: usize) -> LineState {
+    while !s.done() {
+        if s.starts_with("*/") {
+            s.pos += 2;
+            depth -= 1;
+            if depth == 0 {
+                s.push(start, TokenKind::Comment);
+                return LineState::Normal;
+            }
+        } else if s.starts_with("/*") {
+            s.pos += 2;
+            depth += 1;
+        } else {
+            s.pos += 1;
+        }
+    }
+    s.push(start, TokenKind::Comment);
+    LineState::BlockComment(depth)
+}
@@ -0,0 +1,141 @@
+use super::{Grammar, LineState, Scanner, Token, TokenKind};
+
+const KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "in", "function", "return", "local", "export", "readonly", "declare", "select", "break",
+    "continue", "time",
+];
+
+const BUILTINS: &[&str] = &[
+    "echo", "printf", "cd", "read", "set", "unset", "shift", "exit", "source", "alias", "test",
+    "eval", "exec", "trap", "wait", "kill", "pwd", "type", "command",
+];
+
+pub struct Shell;
+
+impl Grammar for Shell {
+    fn highlight_line(&self, line: &str, state: &LineState) -> (Vec<Token>, LineState) {
+        let mut s = Scanner::new(line);
+        let mut state = state.clone();
+        if let LineState::HereDoc(delimiter, strip_tabs) = &state {
+            let body = if *strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            s.rest_as(TokenKind::String);
+            if body == delimiter {
+                return (s.tokens, LineState::Normal);
+            }
+            return (s.tokens, state);
+        }
+        let mut heredoc = None;
+        while !s.done() {
+            state = match state {
+                LineState::String(quote) => string(&mut s, 0, quote),
+                _ => normal(&mut s, &mut heredoc),
+            };
+        }
+        match (state, heredoc) {
+            (LineState::Normal, Some(heredoc)) => (s.tokens, heredoc),
+            (state, _) => (s.tokens, state),
+        }
+    }
+}
+
+fn normal(s: &mut Scanner, heredoc: &mut Option<LineState>) -> LineState {
+    let start = s.pos;
+    match s.peek().unwrap() {
+        b'#' if start == 0 || s.bytes[start - 1].is_ascii_whitespace() => {
+            s.rest_as(TokenKind::Comment)
+        }
+        quote @ (b'"' | b'\'') => {
+            s.pos += 1;
+            return string(s, start, quote);
+        }
+        b'$' => variable(s),
+        b'<' if s.starts_with("<<") && !s.starts_with("<<<") => {
+            s.pos += 2;
+            let strip_tabs = s.peek() == Some(b'-');
+            if strip_tabs {
+                s.pos += 1;
+            }
+            s.eat_while(|c| c == b' ');
+            let word_start = s.pos;
+            s.eat_while(|c| !c.is_ascii_whitespace() && c != b';');
+            let delimiter = String::from_utf8_lossy(&s.bytes[word_start..s.pos])
+                .trim_matches(['\'', '"'])
+                .to_string();
+            s.push(start, TokenKind::Keyword);
+            if !delimiter.is_empty() {
+                *heredoc = Some(LineState::HereDoc(delimiter, strip_tabs));
+            }
+        }
+        c if c.is_ascii_digit() && !s.prev_is_word() => {
+            s.eat_while(|c| c.is_ascii_digit());
+            if s.peek().is_none_or(|c| !super::is_word(c)) {
+                s.push(start, TokenKind::Number);
+            }
+        }
+        c if super::is_word(c) => {
+            let word = s.word();
+            let command_position = s.bytes[..start]
+                .iter()
+                .rev()
+                .find(|c| !c.is_ascii_whitespace())
+                .is_none_or(|c| b";|&({".contains(c));
+            if s.peek() == Some(b'=') && command_position {
+                s.push(start, TokenKind::Variable);
+            } else if KEYWORDS.contains(&word) {
+                s.push(start, TokenKind::Keyword);
+            } else if BUILTINS.contains(&word) && command_position || s.starts_with("()") {
+                s.push(start, TokenKind::Function);
+            }
+        }
+        _ => s.pos += 1,
+    }
+    LineState::Normal
+}
+
+fn variable(s: &mut Scanner) {
+    let start = s.pos;
+    s.pos += 1;
+    match s.peek() {
+        Some(b'{') => {
+            s.eat_while(|c| c != b'}');
+            s.pos = (s.pos + 1).min(s.bytes.len());
+        }
+        Some(b'(') => s.pos += 1,
+        Some(c) if b"?#@*$!-0123456789".contains(&c) => s.pos += 1,
+        _ => s.eat_while(super::is_word),
+    }
+    s.push(start, TokenKind::Variable);
+}
+
+fn string(s: &mut Scanner, start: usize, quote: u8) -> LineState {
+    if quote == b'\'' {
+        if s.string_body(start, quote, false) {
+            return LineState::Normal;
+        }
+        return LineState::String(quote);
+    }
+    let mut segment_start = start;
+    while let Some(c) = s.peek() {
+        match c {
+            b'\\' => s.pos = (s.pos + 2).min(s.bytes.len()),
+            b'$' => {
+                s.push(segment_start, TokenKind::String);
+                variable(s);
+                segment_start = s.pos;
+            }
+            b'"' => {
+                s.pos += 1;
+                s.push(segment_start, TokenKind::String);
+                return LineState::Normal;
+            }
+            _ => s.pos += 1,
+        }
+    }
+    s.push(segment_start, TokenKind::String);
+    LineState::String(quote)
+}
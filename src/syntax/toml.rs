@@ -0,0 +1,112 @@
+use super::{Grammar, LineState, Scanner, Token, TokenKind};
+
+pub struct Toml;
+
+impl Grammar for Toml {
+    fn highlight_line(&self, line: &str, state: &LineState) -> (Vec<Token>, LineState) {
+        let mut s = Scanner::new(line);
+        let mut state = state.clone();
+        // only a line that starts outside a string can begin with a key or a section
+        if let LineState::MultiString(quote) = state {
+            state = multi_string(&mut s, 0, quote);
+        } else {
+            s.eat_while(|c| c == b' ' || c == b'\t');
+            if s.peek() == Some(b'[') {
+                let start = s.pos;
+                s.eat_while(|c| c != b'#');
+                while s.pos > start && s.bytes[s.pos - 1] == b' ' {
+                    s.pos -= 1;
+                }
+                s.push(start, TokenKind::Section);
+            } else if let Some(eq) = key_end(s.bytes) {
+                let start = s.pos;
+                s.pos = eq;
+                s.push(start, TokenKind::Key);
+            }
+        }
+        while !s.done() && state == LineState::Normal {
+            state = value(&mut s);
+        }
+        (s.tokens, state)
+    }
+}
+
+fn key_end(bytes: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    for (i, &c) in bytes.iter().enumerate() {
+        match (quote, c) {
+            (None, b'"' | b'\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, b'=') => return Some(i),
+            (None, b'#' | b'[' | b'{') => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn value(s: &mut Scanner) -> LineState {
+    let start = s.pos;
+    match s.peek().unwrap() {
+        b'#' => s.rest_as(TokenKind::Comment),
+        b'"' if s.starts_with("\"\"\"") => {
+            s.pos += 3;
+            return multi_string(s, start, b'"');
+        }
+        b'\'' if s.starts_with("'''") => {
+            s.pos += 3;
+            return multi_string(s, start, b'\'');
+        }
+        quote @ (b'"' | b'\'') => {
+            s.pos += 1;
+            s.string_body(start, quote, quote == b'"');
+        }
+        c if c.is_ascii_digit() || c == b'+' || c == b'-' => {
+            s.eat_while(|c| c.is_ascii_alphanumeric() || b"+-_.:".contains(&c));
+            s.push(start, TokenKind::Number);
+        }
+        c if super::is_word(c) => {
+            let word = s.word();
+            if matches!(word, "true" | "false" | "inf" | "nan") {
+                s.push(start, TokenKind::Constant);
+            }
+        }
+        _ => s.pos += 1,
+    }
+    LineState::Normal
+}
+
+fn multi_string(s: &mut Scanner, start: usize, quote: u8) -> LineState {
+    let closing = if quote == b'"' { "\"\"\"" } else { "'''" };
+    while !s.done() {
+        if quote == b'"' && s.peek() == Some(b'\\') {
+            s.pos = (s.pos + 2).min(s.bytes.len());
+            continue;
+        }
+        if s.starts_with(closing) {
+            s.pos += 3;
+            s.push(start, TokenKind::String);
+            return LineState::Normal;
+        }
+        s.pos += 1;
+    }
+    s.push(start, TokenKind::String);
+    LineState::MultiString(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_closed_before_equals() {
+        let state = LineState::MultiString(b'"');
+        let (tokens, state) = Toml.highlight_line(r#"end""" = 1"#, &state);
+        assert_eq!(state, LineState::Normal);
+        let kinds: Vec<_> = tokens.iter().map(|t| (t.start, t.end, t.kind)).collect();
+        assert_eq!(
+            kinds,
+            [(0, 6, TokenKind::String), (9, 10, TokenKind::Number)]
+        );
+    }
+}
@@ -1,14 +1,16 @@
 use crate::{
+    backend::{Backend, CursorShape},
     buffer::TextBuffer,
     editor::EditorModes,
-    error::{AppError, Result},
+    error::Result,
+    options::Options,
+    screen::{Cell, Screen},
+    syntax::Token,
+    theme::{ColorDepth, Group, Theme},
+    window::Rect,
 };
-use std::{
-    io::{self, Read, Write, stdout},
-    os::fd::AsRawFd,
-};
-use termios::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -18,90 +20,116 @@ impl Position {
         Self { x: 0, y: 0 }
     }
 }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListChars {
+    pub tab: Option<(char, char)>,
+    pub trail: Option<char>,
+    pub eol: Option<char>,
+    pub nbsp: Option<char>,
+}
+impl Default for ListChars {
+    fn default() -> Self {
+        Self {
+            tab: None,
+            trail: None,
+            eol: Some('$'),
+            nbsp: None,
+        }
+    }
+}
+impl ListChars {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut listchars = Self {
+            tab: None,
+            trail: None,
+            eol: None,
+            nbsp: None,
+        };
+        for item in value.split(',').filter(|item| !item.is_empty()) {
+            let (name, chars) = item.split_once(':')?;
+            let chars: Vec<char> = chars.chars().collect();
+            match (name, chars.as_slice()) {
+                ("tab", [head, fill]) => listchars.tab = Some((*head, *fill)),
+                ("trail", [c]) => listchars.trail = Some(*c),
+                ("eol", [c]) => listchars.eol = Some(*c),
+                ("nbsp", [c]) => listchars.nbsp = Some(*c),
+                _ => return None,
+            }
+        }
+        Some(listchars)
+    }
+}
+
+// a window to draw: its buffer, cursor and scroll position, and its place on the screen
+pub struct View<'a> {
+    pub buffer: &'a TextBuffer,
+    pub pos: Position,
+    pub camera: &'a mut Position,
+    pub rect: Rect,
+    pub current: bool,
+}
+
 pub struct Terminal {
-    termios: Termios,
+    backend: Box<dyn Backend>,
     pub size: Position,
-    pub camera: Position,
     pub cursor: Position,
     line_no_digits: usize,
     pub status_line_left: String,
     pub command_line: String,
     pub status_line_right: String,
-    cursor_type: CursorType,
+    cursor_type: CursorShape,
     is_start_first_time: bool,
-}
-enum CursorType {
-    Ibeam,
-    Block,
+    tabstop: usize,
+    list: bool,
+    listchars: ListChars,
+    number: bool,
+    cursorline: bool,
+    colorcolumn: Vec<usize>,
+    syntax: bool,
+    theme: Theme,
+    color_depth: ColorDepth,
+    pub messages: Vec<String>,
+    // a label for each tab page and whether it is the current one, shown when there are two
+    pub tabline: Vec<(String, bool)>,
+    // asked below the messages instead of the continue prompt
+    pub prompt: Option<String>,
+    screen: Screen,
+    drawn_camera_y: usize,
 }
 
 impl Terminal {
-    pub fn new(buffer_len: usize, filename: &str) -> Result<Self> {
+    pub fn new(buffer_len: usize, filename: &str, mut backend: Box<dyn Backend>) -> Result<Self> {
         let line_no_digits = Self::get_line_no_padding(buffer_len);
-        let fd = io::stdin().as_raw_fd();
-        let mut terminal = Self {
+        let size = backend.size()?;
+        let screen = Screen::new(size.x, size.y);
+        Ok(Self {
+            backend,
             line_no_digits,
             command_line: String::new(),
             is_start_first_time: true,
-            termios: Termios::from_fd(fd)?,
-            size: Position { x: 0, y: 0 },
-            camera: Position { x: 0, y: 0 },
+            size,
             cursor: Position { x: 0, y: 0 },
             status_line_right: String::new(),
             status_line_left: String::from(filename),
-            cursor_type: CursorType::Block,
-        };
-
-        Self::enable_raw_mode(fd)?;
-        terminal.size = Self::get_window_size(&terminal)?;
-        Ok(terminal)
-    }
-
-    fn enable_raw_mode(fd: i32) -> Result<()> {
-        write!(io::stdout(), "\x1b[?1049h").expect("write");
-        stdout().flush().expect("flush");
-
-        let mut termios = Termios::from_fd(fd)?;
-        termios.c_iflag &= !(INPCK | ISTRIP | BRKINT | IXON | ICRNL);
-        termios.c_oflag &= !(OPOST);
-        termios.c_cflag |= CS8;
-        termios.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
-        termios.c_cc[VMIN] = 0;
-        termios.c_cc[VTIME] = 1;
-
-        tcsetattr(fd, TCSAFLUSH, &termios)?;
-        Ok(())
-    }
-
-    fn get_window_size(&self) -> Result<Position> {
-        write!(io::stdout(), "\x1b[999C\x1b[999B")?;
-        stdout().flush()?;
-        Self::get_cursor_pos()
-    }
-
-    fn get_cursor_pos() -> Result<Position> {
-        let mut response = String::new();
-        write!(io::stdout(), "\x1b[6n")?;
-        stdout().flush()?;
-        let mut buf = [0; 1];
-        loop {
-            io::stdin().read_exact(&mut buf)?;
-            let c = buf[0] as char;
-            if c == 'R' {
-                break;
-            }
-            response.push(c);
-        }
-        if !response.starts_with("\x1b[") {
-            return Err(AppError::TermError);
-        }
-        let parts: Vec<&str> = response[2..].split(';').collect();
-        let rows = parts[0].parse::<usize>()?;
-        let cols = parts[1].parse::<usize>()?;
-        Ok(Position { x: cols, y: rows })
+            cursor_type: CursorShape::Block,
+            tabstop: 8,
+            list: false,
+            listchars: ListChars::default(),
+            number: true,
+            cursorline: true,
+            colorcolumn: Vec::new(),
+            syntax: true,
+            theme: Theme::default(),
+            color_depth: ColorDepth::detect(),
+            messages: Vec::new(),
+            tabline: Vec::new(),
+            prompt: None,
+            screen,
+            drawn_camera_y: 0,
+        })
     }
 
-    fn render_start_page(&self, abuf: &mut String) {
+    fn render_start_page(&mut self) {
         let content = [
             "██████╗ ██╗   ██╗ ██████╗████████╗██╗   ██╗   ██╗   ██╗██╗███╗   ███╗",
             "██╔══██╗██║   ██║██╔════╝╚══██╔══╝╚██╗ ██╔╝   ██║   ██║██║████╗ ████║",
@@ -119,203 +147,380 @@ impl Terminal {
             "type  i               to enter insert mode",
         ];
 
-        abuf.push_str("\x1b[38;2;129;161;193m");
-        abuf.push_str("\x1b[48;2;46;52;64m");
-        abuf.push_str("\x1b[2J");
-        let repeat = (self.size.y.saturating_sub(content.len() + 4)) / 3;
-        let space = "\r\n".repeat(repeat);
-        abuf.push_str(&space);
-        for line in &content {
-            let repeat = (self.size.x.saturating_sub(line.chars().count())) / 2;
-            let space = " ".repeat(repeat);
-            abuf.push_str(&space);
-            abuf.push_str(line);
-            abuf.push_str(&space);
-            abuf.push_str("\r\n");
+        let top = (self.size.y.saturating_sub(content.len() + 4)) / 3;
+        for (i, line) in content.iter().enumerate() {
+            let x = (self.size.x.saturating_sub(line.chars().count())) / 2;
+            self.screen
+                .put_str(x, top + i, line, Group::Intro, Group::Normal);
         }
     }
 
-    fn render_rows(&mut self, buffer: &TextBuffer, abuf: &mut String, mode: EditorModes) {
-        if self.is_start_first_time && buffer.rows.is_empty() {
-            self.render_start_page(abuf);
-            abuf.push_str("\x1b[999B");
-            self.render_command_line(abuf);
-            abuf.push_str("\r\x1b[A");
-            self.render_status_line(abuf, &buffer.pos, "", mode);
-            return;
-        }
-        self.is_start_first_time = false;
-        self.line_no_digits = Self::get_line_no_padding(buffer.rows.len());
-
-        let camera_y_end = self.camera.y + self.size.y - 2;
-        for y in self.camera.y..camera_y_end {
-            if let Some(line) = buffer.rows.get(y) {
-                if self.cursor.y + self.camera.y == y {
-                    abuf.push_str("\x1b[48;2;76;86;106m");
-                    abuf.push_str("\x1b[38;2;129;161;193m");
+    fn render_rows(&mut self, view: &View, mode: EditorModes) {
+        let rect = view.rect;
+        let right = rect.x + rect.width;
+        for row in 0..rect.height.saturating_sub(1) {
+            let y = view.camera.y + row;
+            let screen_row = rect.y + row;
+            if let Some(line) = view.buffer.rows.get(y) {
+                let is_cursor_line = self.cursorline && view.pos.y == y;
+                let (bg, number_fg) = if is_cursor_line {
+                    (Group::CursorLine, Group::CursorLineNr)
                 } else {
-                    abuf.push_str("\x1b[48;2;46;52;64m");
-                    abuf.push_str("\x1b[38;2;76;86;106m");
+                    (Group::Normal, Group::LineNr)
+                };
+                self.screen
+                    .fill(screen_row, rect.x, right, Group::Normal, bg);
+                let mut x = rect.x;
+                if self.number {
+                    let number = format!("{:>1$} |", y + 1, self.line_no_digits);
+                    x = self.put_clipped(x, screen_row, &number, right, number_fg, Group::Normal);
                 }
-                abuf.push_str("\x1b[K"); //clears from current position to end of line
-                abuf.push_str("\x1b[48;2;46;52;64m");
-                abuf.push('\r');
-                abuf.push_str(&format!("{:>1$} |", y + 1, self.line_no_digits,));
-                abuf.push_str("\x1b[38;2;216;222;233m");
-                if self.cursor.y + self.camera.y == y {
-                    abuf.push_str("\x1b[48;2;76;86;106m");
+                let tokens = if self.syntax {
+                    view.buffer.syntax.tokens(y)
                 } else {
-                    abuf.push_str("\x1b[48;2;46;52;64m");
-                }
-                abuf.push_str(line);
-                abuf.push_str("\r\n");
+                    &[]
+                };
+                self.render_line(line, tokens, x, right, screen_row, bg);
             } else {
-                abuf.push_str("\x1b[48;2;46;52;64m");
-                abuf.push_str("\x1b[K"); //clears from current position to end of line
-                abuf.push_str("\x1b[38;2;76;86;106m");
-                abuf.push_str("~\r\n");
+                self.put_clipped(
+                    rect.x,
+                    screen_row,
+                    "~",
+                    right,
+                    Group::EndOfBuffer,
+                    Group::Normal,
+                );
             }
         }
 
-        let filename = &buffer.filename.as_ref().map_or("", |s| s.as_str());
-        self.render_status_line(abuf, &buffer.pos, filename, mode);
-        abuf.push_str("\r\n");
-        self.render_command_line(abuf);
+        let buffer = view.buffer;
+        let mut filename = buffer.filename.clone().unwrap_or_default();
+        if !buffer.is_modifiable() {
+            filename.push_str(" [-]");
+        }
+        if buffer.is_readonly() {
+            filename.push_str(" [RO]");
+        }
+        if let Some(progress) = buffer.load_progress() {
+            filename.push_str(&format!(" [loading {}%]", progress));
+        }
+        self.render_status_line(view, &filename, mode);
     }
 
-    fn get_mode_color(&self, mode: EditorModes) -> &str {
-        match mode {
-            EditorModes::Insert => "\r\x1b[48;2;163;190;140m",
-            EditorModes::Normal => "\r\x1b[48;2;129;161;193m",
-            EditorModes::Command => "\r\x1b[48;2;208;135;112m",
-        }
+    // text cut off at column right, returns the column after it
+    fn put_clipped(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        right: usize,
+        fg: Group,
+        bg: Group,
+    ) -> usize {
+        let text: String = text.chars().take(right.saturating_sub(x)).collect();
+        self.screen.put_str(x, y, &text, fg, bg)
     }
 
-    fn render_status_line(
-        &self,
-        abuf: &mut String,
-        pos: &Position,
-        filename: &str,
-        mode: EditorModes,
+    fn render_line(
+        &mut self,
+        line: &str,
+        tokens: &[Token],
+        left: usize,
+        right: usize,
+        row: usize,
+        bg: Group,
     ) {
-        abuf.push_str("\x1b[K"); //clears from current position to end of line
-        let spaces = " ".repeat(self.size.x);
-        abuf.push_str("\x1b[38;2;236;239;244m");
-        abuf.push_str("\x1b[48;2;76;86;106m");
-        abuf.push_str(&spaces);
-        abuf.push_str("\r\x1b[38;2;46;52;64m");
-        abuf.push_str(self.get_mode_color(mode));
-        abuf.push_str(&self.status_line_left);
-        abuf.push_str("\x1b[38;2;236;239;244m");
-        abuf.push_str("\x1b[48;2;76;86;106m ");
-        abuf.push_str(filename);
-        abuf.push_str(&format!("\r\x1b[{}C", self.size.x - 8));
-        let spaces = " ".repeat(8);
-        abuf.push_str(&spaces);
-        abuf.push_str(self.get_mode_color(mode));
-        abuf.push_str("\r\x1b[38;2;46;52;64m");
-        abuf.push_str(&format!(
-            "\r\x1b[{}C",
-            self.size.x - ((pos.y + 1).to_string().len() + (pos.x + 1).to_string().len() + 3)
-        ));
-        abuf.push_str(&format!(" {}:{} ", pos.y + 1, pos.x + 1));
+        let trail_start = line.trim_end_matches(' ').len();
+        let mut tokens = tokens.iter().peekable();
+        let mut cells: Vec<(char, Group)> = Vec::new();
+        for (i, c) in line.char_indices() {
+            while tokens.next_if(|token| token.end <= i).is_some() {}
+            let fg = tokens
+                .peek()
+                .filter(|token| token.start <= i)
+                .map_or(Group::Normal, |token| Group::for_token(token.kind));
+            match c {
+                '\t' => {
+                    let width = self.tab_width(cells.len());
+                    match self.listchars.tab {
+                        Some((head, fill)) if self.list => {
+                            cells.push((head, Group::Whitespace));
+                            cells.extend(std::iter::repeat_n((fill, Group::Whitespace), width - 1));
+                        }
+                        None if self.list => {
+                            cells.extend([('^', Group::Whitespace), ('I', Group::Whitespace)])
+                        }
+                        _ => cells.extend(std::iter::repeat_n((' ', Group::Normal), width)),
+                    }
+                }
+                ' ' if self.list && i >= trail_start => match self.listchars.trail {
+                    Some(trail) => cells.push((trail, Group::Whitespace)),
+                    None => cells.push((c, Group::Normal)),
+                },
+                '\u{a0}' if self.list => match self.listchars.nbsp {
+                    Some(nbsp) => cells.push((nbsp, Group::Whitespace)),
+                    None => cells.push((c, fg)),
+                },
+                _ => cells.push((c, fg)),
+            }
+        }
+        if self.list
+            && let Some(eol) = self.listchars.eol
+        {
+            cells.push((eol, Group::Whitespace));
+        }
+        if let Some(&last) = self.colorcolumn.iter().max() {
+            while cells.len() < last {
+                cells.push((' ', Group::Normal));
+            }
+        }
+        for (col, (c, fg)) in cells.into_iter().enumerate() {
+            if left + col >= right {
+                break;
+            }
+            let bg = if self.colorcolumn.contains(&(col + 1)) {
+                Group::ColorColumn
+            } else {
+                bg
+            };
+            self.screen.set(left + col, row, Cell::new(c, fg, bg));
+        }
     }
 
-    fn render_command_line(&self, abuf: &mut String) {
-        abuf.push('\r');
-        abuf.push_str("\x1b[48;2;46;52;64m");
-        abuf.push_str("\x1b[38;2;216;222;233m");
-        abuf.push_str("\x1b[K"); //clears from current position to end of line
-        abuf.push_str(&self.command_line);
-        abuf.push_str(&format!(
-            "\x1b[{};{}H",
-            self.size.y,
-            self.size.x - self.status_line_right.len()
-        ));
-        abuf.push_str(&self.status_line_right);
+    fn tab_width(&self, col: usize) -> usize {
+        if self.list && self.listchars.tab.is_none() {
+            return 2;
+        }
+        self.tabstop - col % self.tabstop
     }
 
-    fn render_cursor_position(&mut self, pos: &Position, abuf: &mut String) {
-        let bottom_ui_size = 2;
-        let left_ui_size = self.line_no_digits + 2;
-        self.cursor.x = pos.x % self.size.x + left_ui_size;
-        self.cursor.y = pos.y.saturating_sub(self.camera.y);
-        if self.cursor.y >= self.size.y - bottom_ui_size {
-            self.camera.y += self
-                .cursor
-                .y
-                .saturating_sub(self.size.y - bottom_ui_size - 1);
-            self.cursor.y = self.size.y - bottom_ui_size - 1;
-        } else if self.cursor.y == 0 && self.camera.y != pos.y {
-            self.camera.y = self
-                .camera
-                .y
-                .saturating_sub(self.camera.y.saturating_sub(pos.y));
+    // pos.x is a byte offset, tabs expand to the next tabstop on screen
+    pub fn display_col(&self, line: &str, x: usize) -> usize {
+        let mut col = 0;
+        for (i, c) in line.char_indices() {
+            if i >= x {
+                break;
+            }
+            if c == '\t' {
+                col += self.tab_width(col);
+            } else {
+                col += 1;
+            }
         }
-        abuf.push_str("\x1b[?25l"); //hide cursor
-        let cursorcode = self.get_cursor_code();
-        abuf.push_str("\x1b[H"); //cursor upperleft
-        abuf.push_str(cursorcode); //cursor upperleft
+        col + x.saturating_sub(line.len())
     }
 
-    fn update_mouse_pos(&self, abuf: &mut String) {
-        abuf.push_str(&format!(
-            "\x1b[{};{}H",
-            self.cursor.y + 1,
-            self.cursor.x + 1
-        ));
-        abuf.push_str("\x1b[?25h");
+    fn get_mode_group(&self, mode: EditorModes) -> Group {
+        match mode {
+            EditorModes::Insert => Group::StatusLineInsert,
+            EditorModes::Normal => Group::StatusLineNormal,
+            EditorModes::Command => Group::StatusLineCommand,
+        }
     }
 
-    pub fn refresh_screen(&mut self, buffer: &TextBuffer, mode: EditorModes) -> Result<()> {
-        let mut abuf = String::new();
-        self.render_cursor_position(&buffer.pos, &mut abuf);
-        self.render_rows(buffer, &mut abuf, mode);
-        self.update_mouse_pos(&mut abuf);
-        write!(io::stdout(), "{}", abuf)?;
-        stdout().flush()?;
-        Ok(())
+    // the current window shows the mode, a separator to the right belongs to the status line
+    fn render_status_line(&mut self, view: &View, filename: &str, mode: EditorModes) {
+        let rect = view.rect;
+        let y = rect.y + rect.height - 1;
+        let right = (rect.x + rect.width + 1).min(self.size.x);
+        let (fill, mode_group, label) = if view.current {
+            let label = self.status_line_left.clone();
+            (Group::StatusLine, self.get_mode_group(mode), label)
+        } else {
+            (Group::StatusLineNC, Group::StatusLineNC, String::new())
+        };
+        self.screen.fill(y, rect.x, right, fill, fill);
+        let x = self.put_clipped(rect.x, y, &label, right, mode_group, mode_group);
+        self.put_clipped(x, y, &format!(" {}", filename), right, fill, fill);
+        let start = right.saturating_sub(8).max(rect.x);
+        self.screen.fill(y, start, right, fill, fill);
+        let position = format!(" {}:{} ", view.pos.y + 1, view.pos.x + 1);
+        let x = right.saturating_sub(position.len()).max(rect.x);
+        self.put_clipped(x, y, &position, right, mode_group, mode_group);
     }
 
-    pub fn read_key(&self) -> Result<u8> {
-        let mut buffer = [0; 4];
-        let bytes_read = io::stdin().read(&mut buffer)?;
-        if bytes_read == 0 {
-            return Ok(0);
+    fn render_tabline(&mut self) {
+        self.screen
+            .fill_row(0, 0, Group::TabLineFill, Group::TabLineFill);
+        let mut x = 0;
+        for (label, current) in self.tabline.clone() {
+            let group = if current {
+                Group::TabLineSel
+            } else {
+                Group::TabLine
+            };
+            x = self
+                .screen
+                .put_str(x, 0, &format!(" {} ", label), group, group);
         }
-        Ok(if buffer[0] == b'\x1b' {
-            self.handle_other_keys(&buffer)
-        } else {
-            buffer[0]
-        })
     }
 
-    fn handle_other_keys(&self, seq: &[u8]) -> u8 {
-        if seq[1] == b'[' {
-            match seq[2] as char {
-                'A' => b'k',
-                'B' => b'j',
-                'C' => b'l',
-                'D' => b'h',
-                _ => b'\x1b',
+    fn render_separators(&mut self, views: &[View]) {
+        for view in views {
+            let rect = view.rect;
+            let x = rect.x + rect.width;
+            if x >= self.size.x {
+                continue;
+            }
+            for y in rect.y..rect.y + rect.height.saturating_sub(1) {
+                self.screen
+                    .set(x, y, Cell::new('|', Group::VertSplit, Group::VertSplit));
             }
+        }
+    }
+
+    fn render_command_line(&mut self) {
+        let y = self.size.y - 1;
+        self.screen.fill_row(y, 0, Group::Normal, Group::Normal);
+        self.screen
+            .put_str(0, y, &self.command_line, Group::Normal, Group::Normal);
+        let x = self.size.x.saturating_sub(self.status_line_right.len());
+        self.screen
+            .put_str(x, y, &self.status_line_right, Group::Normal, Group::Normal);
+    }
+
+    // scrolls the view to keep its cursor in sight, returns where the cursor is on the screen
+    fn render_cursor_position(&mut self, view: &mut View) -> Position {
+        let pos = &view.pos;
+        let rect = view.rect;
+        let text_rows = rect.height.saturating_sub(1).max(1);
+        let left_ui_size = if self.number {
+            self.line_no_digits + 2
         } else {
-            b'\x1b'
+            0
+        };
+        let display_x = view
+            .buffer
+            .rows
+            .get(pos.y)
+            .map_or(pos.x, |line| self.display_col(line, pos.x));
+        let camera = &mut *view.camera;
+        let mut cursor = Position {
+            x: rect.x + display_x % rect.width.max(1) + left_ui_size,
+            y: pos.y.saturating_sub(camera.y),
+        };
+        if cursor.y >= text_rows {
+            camera.y += cursor.y.saturating_sub(text_rows - 1);
+            cursor.y = text_rows - 1;
+        } else if cursor.y == 0 && camera.y != pos.y {
+            camera.y = camera.y.saturating_sub(camera.y.saturating_sub(pos.y));
         }
+        cursor.y += rect.y;
+        cursor
     }
 
-    fn get_cursor_code(&self) -> &str {
-        match self.cursor_type {
-            CursorType::Block => "\x1b[2 q",
-            CursorType::Ibeam => "\x1b[6 q",
+    fn apply_options(&mut self, buffer: &TextBuffer, options: &Options) {
+        self.tabstop = buffer.options.get_number("tabstop");
+        self.list = options.get_bool("list");
+        self.listchars =
+            ListChars::parse(&options.get_list("listchars").join(",")).unwrap_or_default();
+        self.number = options.get_bool("number");
+        self.cursorline = options.get_bool("cursorline");
+        self.syntax = options.get_bool("syntax");
+        let depth = match (self.color_depth, options.get_bool("termguicolors")) {
+            (ColorDepth::Mono, _) => ColorDepth::Mono,
+            (_, true) => ColorDepth::TrueColor,
+            (ColorDepth::TrueColor, false) => ColorDepth::Ansi256,
+            (depth, false) => depth,
+        };
+        if depth != self.theme.depth {
+            self.theme.depth = depth;
+            self.backend.invalidate();
         }
+        self.colorcolumn = options
+            .get_string("colorcolumn")
+            .split(',')
+            .filter_map(|col| col.parse().ok())
+            .collect();
+        self.line_no_digits = Self::get_line_no_padding(buffer.rows.len());
     }
+
+    fn render_messages(&mut self) {
+        let start = self.size.y.saturating_sub(self.messages.len() + 1);
+        let skip = self.messages.len().saturating_sub(self.size.y - 1);
+        for (i, message) in self.messages.iter().skip(skip).enumerate() {
+            self.screen
+                .fill_row(start + i, 0, Group::Normal, Group::Normal);
+            self.screen
+                .put_str(0, start + i, message, Group::Normal, Group::Normal);
+        }
+        let prompt = self
+            .prompt
+            .as_deref()
+            .unwrap_or("Press ENTER or type command to continue");
+        self.screen
+            .fill_row(self.size.y - 1, 0, Group::Normal, Group::Normal);
+        self.screen
+            .put_str(0, self.size.y - 1, prompt, Group::MoreMsg, Group::Normal);
+        self.cursor.y = self.size.y - 1;
+        self.cursor.x = prompt.len();
+    }
+
+    pub fn refresh_screen(
+        &mut self,
+        views: &mut [View],
+        mode: EditorModes,
+        options: &Options,
+    ) -> Result<()> {
+        for y in 0..self.size.y {
+            self.screen.fill_row(y, 0, Group::Normal, Group::Normal);
+        }
+        let single = views.len() == 1;
+        for view in views.iter_mut() {
+            self.apply_options(view.buffer, options);
+            let cursor = self.render_cursor_position(view);
+            if view.current {
+                self.cursor = cursor;
+            }
+            if single && self.is_start_first_time && view.buffer.rows.is_empty() {
+                self.render_start_page();
+                self.render_status_line(view, "", mode);
+                continue;
+            }
+            self.is_start_first_time = false;
+            self.render_rows(view, mode);
+        }
+        // only a window as wide as the screen can be scrolled by the terminal
+        if let [view] = views {
+            let text_rows = view.rect.height.saturating_sub(1);
+            let scrolled = view.camera.y as isize - self.drawn_camera_y as isize;
+            if scrolled.unsigned_abs() < text_rows / 2 {
+                self.backend
+                    .scroll(view.rect.y, view.rect.y + text_rows, scrolled);
+            }
+            self.drawn_camera_y = view.camera.y;
+        }
+        self.render_separators(views);
+        if !self.tabline.is_empty() {
+            self.render_tabline();
+        }
+        self.render_command_line();
+        if !self.messages.is_empty() {
+            self.render_messages();
+        }
+        self.backend
+            .draw(&self.screen, &self.theme, &self.cursor, self.cursor_type)
+    }
+
+    pub fn read_key(&mut self) -> Result<u8> {
+        self.backend.read_key()
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.backend.invalidate();
+    }
+
     pub fn change_cursor(&mut self, mode: EditorModes) {
         match mode {
             EditorModes::Insert => {
-                self.cursor_type = CursorType::Ibeam;
+                self.cursor_type = CursorShape::Bar;
             }
-            _ => self.cursor_type = CursorType::Block,
+            _ => self.cursor_type = CursorShape::Block,
         }
     }
 
@@ -323,10 +528,3 @@ impl Terminal {
         buffer_len.checked_ilog10().unwrap_or(0) as usize + 1
     }
 }
-impl Drop for Terminal {
-    fn drop(&mut self) {
-        tcsetattr(io::stdin().as_raw_fd(), TCSAFLUSH, &self.termios).expect("tcsetattr");
-        write!(io::stdout(), "\x1b[?1049l").expect("write");
-        stdout().flush().expect("flush");
-    }
-}
@@ -0,0 +1,399 @@
+use crate::{config, syntax::TokenKind};
+use std::{env, fs, path::PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorDepth {
+    #[default]
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    Mono,
+}
+
+impl ColorDepth {
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorDepth::Mono;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        match env::var("TERM").unwrap_or_default().as_str() {
+            "" | "dumb" => ColorDepth::Mono,
+            term if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let hex = value.strip_prefix('#')?;
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        Some(Self::rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    fn distance(self, other: Color) -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        d(self.r, other.r) + d(self.g, other.g) + d(self.b, other.b)
+    }
+
+    // nearest entry of the 6x6x6 cube or the 24 step grey ramp
+    fn to_ansi256(self) -> u8 {
+        let level = |v: u8| match v {
+            0..48 => 0,
+            48..115 => 1,
+            _ => (v - 35) / 40,
+        };
+        let value = |i: u8| if i == 0 { 0 } else { 55 + i * 40 };
+        let (r, g, b) = (level(self.r), level(self.g), level(self.b));
+        let cube = Color::rgb(value(r), value(g), value(b));
+        let average = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+        let grey_index = (average.saturating_sub(3) / 10).min(23) as u8;
+        let grey = Color::rgb(
+            8 + grey_index * 10,
+            8 + grey_index * 10,
+            8 + grey_index * 10,
+        );
+        if self.distance(grey) < self.distance(cube) {
+            232 + grey_index
+        } else {
+            16 + 36 * r + 6 * g + b
+        }
+    }
+
+    // nearest distance turns pastel colours grey, so pick by which channels stand out instead
+    fn to_ansi16(self) -> u8 {
+        let max = self.r.max(self.g).max(self.b) as u32;
+        let min = self.r.min(self.g).min(self.b) as u32;
+        let grey_threshold = if max < 140 { 30 } else { 20 };
+        if (max - min) * 100 < max * grey_threshold {
+            let average = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+            return match average {
+                0..64 => 0,
+                64..150 => 8,
+                150..220 => 7,
+                _ => 15,
+            };
+        }
+        let mid = (max + min) / 2;
+        let index = (self.r as u32 > mid) as u8
+            | ((self.g as u32 > mid) as u8) << 1
+            | ((self.b as u32 > mid) as u8) << 2;
+        if max >= 160 { index + 8 } else { index }
+    }
+
+    fn escape(self, depth: ColorDepth, background: bool) -> String {
+        let layer = if background { 48 } else { 38 };
+        match depth {
+            ColorDepth::TrueColor => {
+                format!("\x1b[{};2;{};{};{}m", layer, self.r, self.g, self.b)
+            }
+            ColorDepth::Ansi256 => format!("\x1b[{};5;{}m", layer, self.to_ansi256()),
+            ColorDepth::Ansi16 => {
+                let base = if background { 40 } else { 30 };
+                match self.to_ansi16() {
+                    i @ 0..8 => format!("\x1b[{}m", base + i),
+                    i => format!("\x1b[{}m", base + 60 + i - 8),
+                }
+            }
+            ColorDepth::Mono => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    Normal,
+    LineNr,
+    CursorLine,
+    CursorLineNr,
+    EndOfBuffer,
+    Whitespace,
+    ColorColumn,
+    StatusLine,
+    StatusLineNormal,
+    StatusLineInsert,
+    StatusLineCommand,
+    StatusLineNC,
+    VertSplit,
+    TabLine,
+    TabLineSel,
+    TabLineFill,
+    MoreMsg,
+    Intro,
+    Search,
+    Visual,
+    Comment,
+    Keyword,
+    Type,
+    Function,
+    String,
+    Number,
+    Constant,
+    Identifier,
+    PreProc,
+    Special,
+    Title,
+}
+
+const GROUPS: &[(Group, &str)] = &[
+    (Group::Normal, "Normal"),
+    (Group::LineNr, "LineNr"),
+    (Group::CursorLine, "CursorLine"),
+    (Group::CursorLineNr, "CursorLineNr"),
+    (Group::EndOfBuffer, "EndOfBuffer"),
+    (Group::Whitespace, "Whitespace"),
+    (Group::ColorColumn, "ColorColumn"),
+    (Group::StatusLine, "StatusLine"),
+    (Group::StatusLineNormal, "StatusLineNormal"),
+    (Group::StatusLineInsert, "StatusLineInsert"),
+    (Group::StatusLineCommand, "StatusLineCommand"),
+    (Group::StatusLineNC, "StatusLineNC"),
+    (Group::VertSplit, "VertSplit"),
+    (Group::TabLine, "TabLine"),
+    (Group::TabLineSel, "TabLineSel"),
+    (Group::TabLineFill, "TabLineFill"),
+    (Group::MoreMsg, "MoreMsg"),
+    (Group::Intro, "Intro"),
+    (Group::Search, "Search"),
+    (Group::Visual, "Visual"),
+    (Group::Comment, "Comment"),
+    (Group::Keyword, "Keyword"),
+    (Group::Type, "Type"),
+    (Group::Function, "Function"),
+    (Group::String, "String"),
+    (Group::Number, "Number"),
+    (Group::Constant, "Constant"),
+    (Group::Identifier, "Identifier"),
+    (Group::PreProc, "PreProc"),
+    (Group::Special, "Special"),
+    (Group::Title, "Title"),
+];
+
+impl Group {
+    fn parse(name: &str) -> Option<Self> {
+        GROUPS
+            .iter()
+            .find(|(_, group_name)| group_name.eq_ignore_ascii_case(name))
+            .map(|(group, _)| *group)
+    }
+
+    pub fn for_token(kind: TokenKind) -> Self {
+        match kind {
+            TokenKind::Keyword | TokenKind::Section => Group::Keyword,
+            TokenKind::Type => Group::Type,
+            TokenKind::Key => Group::Identifier,
+            TokenKind::Function | TokenKind::Macro | TokenKind::Link => Group::Function,
+            TokenKind::String | TokenKind::Code => Group::String,
+            TokenKind::Number => Group::Number,
+            TokenKind::Constant => Group::Constant,
+            TokenKind::Comment => Group::Comment,
+            TokenKind::Attribute => Group::PreProc,
+            TokenKind::Escape | TokenKind::Variable | TokenKind::Emphasis => Group::Special,
+            TokenKind::Heading => Group::Title,
+        }
+    }
+}
+
+const NORD0: Color = Color::rgb(46, 52, 64);
+const NORD1: Color = Color::rgb(59, 66, 82);
+const NORD2: Color = Color::rgb(67, 76, 94);
+const NORD3: Color = Color::rgb(76, 86, 106);
+const NORD3_BRIGHT: Color = Color::rgb(97, 110, 136);
+const NORD4: Color = Color::rgb(216, 222, 233);
+const NORD6: Color = Color::rgb(236, 239, 244);
+const NORD7: Color = Color::rgb(143, 188, 187);
+const NORD8: Color = Color::rgb(136, 192, 208);
+const NORD9: Color = Color::rgb(129, 161, 193);
+const NORD12: Color = Color::rgb(208, 135, 112);
+const NORD13: Color = Color::rgb(235, 203, 139);
+const NORD14: Color = Color::rgb(163, 190, 140);
+const NORD15: Color = Color::rgb(180, 142, 173);
+
+const fn fg(color: Color) -> Style {
+    Style {
+        fg: Some(color),
+        bg: None,
+    }
+}
+
+const fn bg(color: Color) -> Style {
+    Style {
+        fg: None,
+        bg: Some(color),
+    }
+}
+
+const fn fg_bg(fg: Color, bg: Color) -> Style {
+    Style {
+        fg: Some(fg),
+        bg: Some(bg),
+    }
+}
+
+const NORD: &[(Group, Style)] = &[
+    (Group::Normal, fg_bg(NORD4, NORD0)),
+    (Group::LineNr, fg(NORD3)),
+    (Group::CursorLine, bg(NORD3)),
+    (Group::CursorLineNr, fg(NORD9)),
+    (Group::EndOfBuffer, fg(NORD3)),
+    (Group::Whitespace, fg(NORD3_BRIGHT)),
+    (Group::ColorColumn, bg(NORD1)),
+    (Group::StatusLine, fg_bg(NORD6, NORD3)),
+    (Group::StatusLineNormal, fg_bg(NORD0, NORD9)),
+    (Group::StatusLineInsert, fg_bg(NORD0, NORD14)),
+    (Group::StatusLineCommand, fg_bg(NORD0, NORD12)),
+    (Group::StatusLineNC, fg_bg(NORD4, NORD1)),
+    (Group::VertSplit, fg_bg(NORD3, NORD1)),
+    (Group::TabLine, fg_bg(NORD4, NORD1)),
+    (Group::TabLineSel, fg_bg(NORD0, NORD9)),
+    (Group::TabLineFill, bg(NORD1)),
+    (Group::MoreMsg, fg(NORD14)),
+    (Group::Intro, fg(NORD9)),
+    (Group::Search, fg_bg(NORD0, NORD13)),
+    (Group::Visual, bg(NORD2)),
+    (Group::Comment, fg(NORD3_BRIGHT)),
+    (Group::Keyword, fg(NORD9)),
+    (Group::Type, fg(NORD7)),
+    (Group::Function, fg(NORD8)),
+    (Group::String, fg(NORD14)),
+    (Group::Number, fg(NORD15)),
+    (Group::Constant, fg(NORD15)),
+    (Group::Identifier, fg(NORD7)),
+    (Group::PreProc, fg(NORD12)),
+    (Group::Special, fg(NORD13)),
+    (Group::Title, fg(NORD8)),
+];
+
+pub struct Theme {
+    pub name: String,
+    pub depth: ColorDepth,
+    styles: Vec<(Group, Style)>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: String::from("nord"),
+            depth: ColorDepth::default(),
+            styles: NORD.to_vec(),
+        }
+    }
+}
+
+impl Theme {
+    // looks for <config dir>/colors/<name>.theme unless name is a path
+    pub fn load(name: &str) -> Result<Self, String> {
+        if name == "nord" {
+            return Ok(Self::default());
+        }
+        let path = if name.contains('/') {
+            PathBuf::from(name)
+        } else {
+            config::config_dir()
+                .ok_or(format!("Cannot find color scheme '{}'", name))?
+                .join("colors")
+                .join(format!("{}.theme", name))
+        };
+        let contents = fs::read_to_string(&path)
+            .map_err(|_| format!("Cannot find color scheme '{}'", name))?;
+        let mut theme = Self {
+            name: path
+                .file_stem()
+                .map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned()),
+            ..Self::default()
+        };
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') {
+                continue;
+            }
+            theme
+                .parse_line(line)
+                .map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+        }
+        Ok(theme)
+    }
+
+    // "Group fg=#rrggbb bg=#rrggbb", NONE clears a colour
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let group = Group::parse(name).ok_or(format!("Unknown highlight group: {}", name))?;
+        let mut style = Style::default();
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or(format!("Invalid argument: {}", word))?;
+            let color = match value {
+                "NONE" => None,
+                _ => Some(Color::parse(value).ok_or(format!("Invalid color: {}", value))?),
+            };
+            match key {
+                "fg" => style.fg = color,
+                "bg" => style.bg = color,
+                _ => return Err(format!("Invalid argument: {}", word)),
+            }
+        }
+        match self.styles.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, existing)) => *existing = style,
+            None => self.styles.push((group, style)),
+        }
+        Ok(())
+    }
+
+    pub fn style(&self, group: Group) -> Style {
+        self.styles
+            .iter()
+            .find(|(g, _)| *g == group)
+            .map_or(Style::default(), |(_, style)| *style)
+    }
+
+    // groups without a foreground fall back to Normal
+    pub fn fg(&self, group: Group) -> String {
+        let color = self
+            .style(group)
+            .fg
+            .or(self.style(Group::Normal).fg)
+            .unwrap_or(Color::rgb(255, 255, 255));
+        color.escape(self.depth, false)
+    }
+
+    // groups without a background fall back to Normal, monochrome shows them in reverse video
+    pub fn bg(&self, group: Group) -> String {
+        let style = self.style(group);
+        if self.depth == ColorDepth::Mono {
+            return match style.bg {
+                Some(_) if group != Group::Normal => String::from("\x1b[7m"),
+                _ => String::from("\x1b[27m"),
+            };
+        }
+        let color = style
+            .bg
+            .or(self.style(Group::Normal).bg)
+            .unwrap_or(Color::rgb(0, 0, 0));
+        color.escape(self.depth, true)
+    }
+}
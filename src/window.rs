@@ -0,0 +1,457 @@
+use crate::terminal::Position;
+use std::collections::HashMap;
+
+// the smallest window: one text row above its status line, or one column
+const MIN_HEIGHT: usize = 2;
+const MIN_WIDTH: usize = 1;
+
+// a view of a buffer with its own cursor and scroll position, the cursor of the current
+// window lives in its buffer until another window is entered
+pub struct Window {
+    pub buffer: usize,
+    pub pos: Position,
+    pub camera: Position,
+}
+
+// a part of the screen, the last row of a window is its status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+// windows side by side when vertical, stacked otherwise, each with its width or height;
+// side by side windows have a separator column between them
+enum Node {
+    Leaf(usize),
+    Split(bool, Vec<(Node, usize)>),
+}
+
+impl Node {
+    fn min_extent(&self, vertical: bool) -> usize {
+        match self {
+            Node::Leaf(_) if vertical => MIN_WIDTH,
+            Node::Leaf(_) => MIN_HEIGHT,
+            Node::Split(dir, children) => {
+                let mins = children.iter().map(|(child, _)| child.min_extent(vertical));
+                if *dir == vertical {
+                    let separators = if vertical { children.len() - 1 } else { 0 };
+                    mins.sum::<usize>() + separators
+                } else {
+                    mins.max().unwrap_or(0)
+                }
+            }
+        }
+    }
+
+    fn rects(&self, area: Rect, out: &mut Vec<(usize, Rect)>) {
+        match self {
+            Node::Leaf(id) => out.push((*id, area)),
+            Node::Split(vertical, children) => {
+                let sizes = children.iter().map(|(_, size)| *size);
+                for ((child, _), rect) in children.iter().zip(child_rects(*vertical, sizes, area)) {
+                    child.rects(rect, out);
+                }
+            }
+        }
+    }
+
+    // puts new before the window id, each taking half of its room
+    fn split(&mut self, id: usize, new: usize, vertical: bool, area: Rect) -> Result<bool, String> {
+        match self {
+            Node::Leaf(leaf) if *leaf == id => {
+                let extent = if vertical { area.width } else { area.height };
+                let (first, second) = halves(extent, vertical)?;
+                *self = Node::Split(
+                    vertical,
+                    vec![(Node::Leaf(new), first), (Node::Leaf(id), second)],
+                );
+                Ok(true)
+            }
+            Node::Leaf(_) => Ok(false),
+            Node::Split(dir, children) => {
+                let sizes: Vec<usize> = children.iter().map(|(_, size)| *size).collect();
+                let rects = child_rects(*dir, sizes.iter().copied(), area);
+                for (i, rect) in rects.into_iter().enumerate() {
+                    if *dir == vertical && matches!(children[i].0, Node::Leaf(leaf) if leaf == id) {
+                        let (first, second) = halves(sizes[i], vertical)?;
+                        children
+                            .splice(i..=i, [(Node::Leaf(new), first), (Node::Leaf(id), second)]);
+                        return Ok(true);
+                    }
+                    if children[i].0.split(id, new, vertical, rect)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    // the room of a closed window goes to the one before it, or after it when it was first
+    fn remove(&mut self, id: usize) -> bool {
+        let Node::Split(vertical, children) = self else {
+            return false;
+        };
+        let found = children
+            .iter()
+            .position(|(child, _)| matches!(child, Node::Leaf(leaf) if *leaf == id));
+        let Some(i) = found else {
+            return children.iter_mut().any(|(child, _)| child.remove(id));
+        };
+        let (_, size) = children.remove(i);
+        let separator = usize::from(*vertical);
+        children[i.saturating_sub(1)].1 += size + separator;
+        if children.len() == 1 {
+            let (child, _) = children.remove(0);
+            *self = child;
+        }
+        true
+    }
+
+    // changes the size of the window id in the innermost split along vertical, the room comes
+    // from the window after it, or before it when it is last; Some(true) once resized
+    fn resize(&mut self, id: usize, vertical: bool, delta: isize) -> Option<bool> {
+        match self {
+            Node::Leaf(leaf) => (*leaf == id).then_some(false),
+            Node::Split(dir, children) => {
+                for i in 0..children.len() {
+                    match children[i].0.resize(id, vertical, delta) {
+                        None => continue,
+                        Some(true) => return Some(true),
+                        Some(false) if *dir != vertical => return Some(false),
+                        Some(false) => {
+                            let j = if i + 1 < children.len() { i + 1 } else { i - 1 };
+                            let shrink = children[i].1 - children[i].0.min_extent(vertical);
+                            let grow = children[j].1 - children[j].0.min_extent(vertical);
+                            let delta = delta.clamp(-(shrink as isize), grow as isize);
+                            children[i].1 = children[i].1.saturating_add_signed(delta);
+                            children[j].1 = children[j].1.saturating_add_signed(-delta);
+                            return Some(true);
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    fn equalize(&mut self, area: Rect) {
+        let Node::Split(vertical, children) = self else {
+            return;
+        };
+        let count = children.len();
+        let extent = if *vertical {
+            area.width.saturating_sub(count - 1)
+        } else {
+            area.height
+        };
+        for (i, (_, size)) in children.iter_mut().enumerate() {
+            *size = extent / count + usize::from(i < extent % count);
+        }
+        let sizes = children.iter().map(|(_, size)| *size).collect::<Vec<_>>();
+        for ((child, _), rect) in
+            children
+                .iter_mut()
+                .zip(child_rects(*vertical, sizes.into_iter(), area))
+        {
+            child.equalize(rect);
+        }
+    }
+
+    // makes the sizes fill area again, the last windows grow or shrink
+    fn refit(&mut self, area: Rect) {
+        let Node::Split(vertical, children) = self else {
+            return;
+        };
+        let vertical = *vertical;
+        let extent = if vertical {
+            area.width.saturating_sub(children.len() - 1)
+        } else {
+            area.height
+        };
+        let total: usize = children.iter().map(|(_, size)| *size).sum();
+        if total < extent {
+            if let Some((_, size)) = children.last_mut() {
+                *size += extent - total;
+            }
+        } else {
+            let mut over = total - extent;
+            for (child, size) in children.iter_mut().rev() {
+                let take = over.min(size.saturating_sub(child.min_extent(vertical)));
+                *size -= take;
+                over -= take;
+            }
+            if let Some((_, size)) = children.last_mut() {
+                *size = size.saturating_sub(over);
+            }
+        }
+        let sizes: Vec<usize> = children.iter().map(|(_, size)| *size).collect();
+        for ((child, _), rect) in
+            children
+                .iter_mut()
+                .zip(child_rects(vertical, sizes.into_iter(), area))
+        {
+            child.refit(rect);
+        }
+    }
+}
+
+fn child_rects(vertical: bool, sizes: impl Iterator<Item = usize>, area: Rect) -> Vec<Rect> {
+    let mut offset = 0;
+    sizes
+        .map(|size| {
+            let rect = if vertical {
+                Rect {
+                    x: area.x + offset,
+                    width: size,
+                    ..area
+                }
+            } else {
+                Rect {
+                    y: area.y + offset,
+                    height: size,
+                    ..area
+                }
+            };
+            offset += size + usize::from(vertical);
+            rect
+        })
+        .collect()
+}
+
+// the sizes of two windows sharing extent, side by side ones lose a column to the separator
+fn halves(extent: usize, vertical: bool) -> Result<(usize, usize), String> {
+    let (room, min) = if vertical {
+        (extent.saturating_sub(1), MIN_WIDTH)
+    } else {
+        (extent, MIN_HEIGHT)
+    };
+    let first = room / 2;
+    if first < min || room - first < min {
+        return Err(String::from("Not enough room"));
+    }
+    Ok((first, room - first))
+}
+
+// the windows on the screen and how they share it
+pub struct Layout {
+    root: Node,
+    windows: HashMap<usize, Window>,
+    current: usize,
+    area: Rect,
+    next_id: usize,
+}
+
+impl Layout {
+    pub fn new(buffer: usize, area: Rect) -> Self {
+        let window = Window {
+            buffer,
+            pos: Position::new(),
+            camera: Position::new(),
+        };
+        Self {
+            root: Node::Leaf(1),
+            windows: HashMap::from([(1, window)]),
+            current: 1,
+            area,
+            next_id: 2,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn current_id(&self) -> usize {
+        self.current
+    }
+
+    pub fn set_current(&mut self, id: usize) {
+        if self.windows.contains_key(&id) {
+            self.current = id;
+        }
+    }
+
+    pub fn window(&self, id: usize) -> &Window {
+        &self.windows[&id]
+    }
+
+    pub fn current(&self) -> &Window {
+        &self.windows[&self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Window {
+        self.windows.get_mut(&self.current).unwrap()
+    }
+
+    pub fn set_area(&mut self, area: Rect) {
+        if area != self.area {
+            self.area = area;
+            self.root.refit(area);
+        }
+    }
+
+    // every window with its place on the screen, from the top left
+    pub fn rects(&self) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.root.rects(self.area, &mut rects);
+        rects
+    }
+
+    // the windows in screen order with their places, and whether each is the current one
+    pub fn visible(&mut self) -> Vec<(Rect, bool, &mut Window)> {
+        let rects = self.rects();
+        let mut windows: HashMap<usize, &mut Window> = self
+            .windows
+            .iter_mut()
+            .map(|(id, window)| (*id, window))
+            .collect();
+        rects
+            .into_iter()
+            .filter_map(|(id, rect)| Some((rect, id == self.current, windows.remove(&id)?)))
+            .collect()
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.rects().into_iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn buffers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.windows.values().map(|window| window.buffer)
+    }
+
+    pub fn shows(&self, buffer: usize) -> bool {
+        self.windows.values().any(|window| window.buffer == buffer)
+    }
+
+    // whether a window other than the current one shows buffer
+    pub fn shows_elsewhere(&self, buffer: usize) -> bool {
+        self.windows
+            .iter()
+            .any(|(id, window)| *id != self.current && window.buffer == buffer)
+    }
+
+    // a copy of the current window above or to the left of it, which becomes current
+    pub fn split(&mut self, vertical: bool) -> Result<(), String> {
+        let id = self.next_id;
+        if !self.root.split(self.current, id, vertical, self.area)? {
+            return Err(String::from("No window to split"));
+        }
+        let current = self.current();
+        let window = Window {
+            buffer: current.buffer,
+            pos: current.pos,
+            camera: current.camera,
+        };
+        self.next_id += 1;
+        self.windows.insert(id, window);
+        self.current = id;
+        self.root.refit(self.area);
+        Ok(())
+    }
+
+    // the window at the same place in the order becomes current when the current one closes
+    pub fn close(&mut self, id: usize) -> Result<(), String> {
+        if self.windows.len() < 2 {
+            return Err(String::from("Cannot close last window"));
+        }
+        let ids = self.ids();
+        let index = ids.iter().position(|other| *other == id).unwrap_or(0);
+        self.root.remove(id);
+        self.windows.remove(&id);
+        self.root.refit(self.area);
+        if self.current == id {
+            let ids = self.ids();
+            self.current = ids[index.saturating_sub(1).min(ids.len() - 1)];
+        }
+        Ok(())
+    }
+
+    pub fn only(&mut self) {
+        self.root = Node::Leaf(self.current);
+        self.windows.retain(|id, _| *id == self.current);
+        self.root.refit(self.area);
+    }
+
+    // ctrl-w w: the next window, or window n
+    pub fn next(&self, n: Option<usize>) -> usize {
+        let ids = self.ids();
+        match n {
+            Some(n) => ids[n.clamp(1, ids.len()) - 1],
+            None => {
+                let index = ids.iter().position(|id| *id == self.current).unwrap_or(0);
+                ids[(index + 1) % ids.len()]
+            }
+        }
+    }
+
+    // the window next to the current one in direction, at the screen cell at, count times over
+    pub fn neighbour(&self, direction: Direction, at: Position, count: usize) -> usize {
+        let rects = self.rects();
+        let mut id = self.current;
+        for _ in 0..count.max(1) {
+            let Some(&(_, from)) = rects.iter().find(|(other, _)| *other == id) else {
+                break;
+            };
+            let next = rects.iter().filter(|(_, rect)| match direction {
+                Direction::Left => rect.x + rect.width + 1 == from.x,
+                Direction::Right => from.x + from.width + 1 == rect.x,
+                Direction::Up => rect.y + rect.height == from.y,
+                Direction::Down => from.y + from.height == rect.y,
+            });
+            let across = |rect: &Rect| match direction {
+                Direction::Left | Direction::Right => (
+                    rect.y.max(from.y),
+                    (rect.y + rect.height).min(from.y + from.height),
+                ),
+                Direction::Up | Direction::Down => (
+                    rect.x.max(from.x),
+                    (rect.x + rect.width + 1).min(from.x + from.width + 1),
+                ),
+            };
+            let at = match direction {
+                Direction::Left | Direction::Right => at.y,
+                Direction::Up | Direction::Down => at.x,
+            };
+            let overlapping: Vec<&(usize, Rect)> = next
+                .filter(|(_, rect)| {
+                    let (start, end) = across(rect);
+                    start < end
+                })
+                .collect();
+            // the window beside the cursor, otherwise the first one beside the current window
+            let found = overlapping
+                .iter()
+                .find(|(_, rect)| {
+                    let (start, end) = across(rect);
+                    (start..end).contains(&at)
+                })
+                .or(overlapping.first());
+            match found {
+                Some((next, _)) => id = *next,
+                None => break,
+            }
+        }
+        id
+    }
+
+    pub fn resize(&mut self, vertical: bool, delta: isize) {
+        self.root.resize(self.current, vertical, delta);
+        self.root.refit(self.area);
+    }
+
+    pub fn equalize(&mut self) {
+        self.root.equalize(self.area);
+    }
+}
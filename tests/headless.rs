@@ -0,0 +1,450 @@
+use rusty_vim::{Editor, Group, HeadlessBackend};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+struct Temp(PathBuf);
+
+impl Drop for Temp {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn scratch(name: &str) -> Temp {
+    let file = Temp(env::temp_dir().join(format!("rusty-vim-{}-{}", process::id(), name)));
+    fs::write(&file.0, "hello\nworld\n").unwrap();
+    file
+}
+
+// rewrites path with text, its modification time moved on by seconds
+fn rewrite(path: &Path, text: &str, seconds: u64) {
+    fs::write(path, text).unwrap();
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(seconds))
+        .unwrap();
+}
+
+// an editor on a scratch file, drawing into a headless screen of width by height
+fn editor(name: &str, width: usize, height: usize) -> (Editor, HeadlessBackend, Temp) {
+    let file = scratch(name);
+    let backend = HeadlessBackend::new(width, height);
+    let mut editor = Editor::with_backend(Vec::new(), Box::new(backend.clone())).unwrap();
+    editor.execute("set noswapfile").unwrap();
+    editor.open(file.0.to_str().unwrap()).unwrap();
+    editor.feed_keys(b"").unwrap();
+    (editor, backend, file)
+}
+
+#[test]
+fn status_line() {
+    let (mut editor, backend, _file) = editor("status", 40, 6);
+    let lines = backend.lines();
+    assert_eq!(lines[..4], ["1 |hello", "2 |world", "~", "~"]);
+    assert!(lines[4].contains("rusty-vim"));
+    assert!(lines[4].ends_with("1:1"));
+    editor.feed_keys(b"j$").unwrap();
+    assert!(backend.lines()[4].ends_with("2:5"));
+    assert_eq!(backend.cursor().0.y, 1);
+    let buffer = editor.buffer().unwrap();
+    assert_eq!((buffer.cursor().y, buffer.cursor().x), (1, 4));
+    assert_eq!(buffer.rows().len(), 2);
+    assert!(buffer.filename().unwrap().ends_with("-status"));
+    assert!(!buffer.is_modified());
+}
+
+#[test]
+fn split_windows() {
+    let (mut editor, backend, _file) = editor("split", 40, 8);
+    editor.execute("vs").unwrap();
+    editor.execute("sp").unwrap();
+    let lines = backend.lines();
+    // the new window is on top of the left column, all three show the file
+    assert_eq!(lines[0], "1 |hello           |1 |hello");
+    assert_eq!(lines[3], "1 |hello           |~");
+    assert_eq!(backend.cell(19, 0).unwrap().ch, '|');
+    assert_eq!(backend.cell(19, 0).unwrap().fg, Group::VertSplit);
+    // a status line for each window, only the current one is highlighted as such
+    assert_eq!(backend.cell(1, 2).unwrap().bg, Group::StatusLine);
+    assert_eq!(backend.cell(1, 6).unwrap().bg, Group::StatusLineNC);
+    assert_eq!(backend.cell(21, 6).unwrap().bg, Group::StatusLineNC);
+}
+
+#[test]
+fn tabline() {
+    let (mut editor, backend, _file) = editor("tabline", 40, 6);
+    assert!(!backend.lines()[0].contains("[No Name]"));
+    editor.execute("vs").unwrap();
+    editor.execute("tabnew").unwrap();
+    let lines = backend.lines();
+    assert_eq!(
+        lines[0],
+        format!(" 2 rusty-vim-{}-tabline  [No Name]", process::id())
+    );
+    assert_eq!(lines[1], "~");
+    let label = lines[0].find("[No Name]").unwrap();
+    assert_eq!(backend.cell(1, 0).unwrap().bg, Group::TabLine);
+    assert_eq!(backend.cell(label, 0).unwrap().bg, Group::TabLineSel);
+    assert_eq!(backend.cell(39, 0).unwrap().bg, Group::TabLineFill);
+    editor.feed_keys(b"gt").unwrap();
+    assert_eq!(backend.cell(1, 0).unwrap().bg, Group::TabLineSel);
+    assert_eq!(backend.lines()[1], "1 |hello           |1 |hello");
+}
+
+#[test]
+fn delete_and_wipe() {
+    let (mut editor, _backend, first) = editor("delete", 40, 6);
+    let second = scratch("delete-other");
+    editor.open(second.0.to_str().unwrap()).unwrap();
+    // `:bd` keeps the file as the alternate, it comes back as buffer 3 after
+    // the empty buffer and the first file
+    editor.execute("bd").unwrap();
+    assert_eq!(editor.buffer().unwrap().filename(), first.0.to_str());
+    editor.execute("b#").unwrap();
+    assert_eq!(editor.buffer().unwrap().filename(), second.0.to_str());
+    editor.execute("bd").unwrap();
+    editor.execute("b3").unwrap();
+    assert_eq!(editor.buffer().unwrap().filename(), second.0.to_str());
+    // `:bw` forgets it
+    editor.execute("bw").unwrap();
+    assert_eq!(editor.execute("b#"), Err(String::from("No alternate file")));
+    assert!(editor.execute("b3").is_err());
+}
+
+#[test]
+fn large_file_not_utf8() {
+    let file = Temp(env::temp_dir().join(format!("rusty-vim-{}-notutf8", process::id())));
+    let mut bytes = b"hello\n".to_vec();
+    for _ in 0..20000 {
+        bytes.extend_from_slice(&[b'x'; 99]);
+        bytes.push(b'\n');
+    }
+    bytes.extend_from_slice(b"\xff\n");
+    fs::write(&file.0, &bytes).unwrap();
+    let copy = Temp(file.0.with_extension("copy"));
+    let backend = HeadlessBackend::new(40, 6);
+    let mut editor = Editor::with_backend(Vec::new(), Box::new(backend)).unwrap();
+    editor.execute("set noswapfile largefile=1").unwrap();
+    editor.open(file.0.to_str().unwrap()).unwrap();
+    editor.feed_keys(b"ihi\x1b").unwrap();
+    // the edit survives finding the bad line, and not even `!` writes it over the file
+    let error = editor.execute("w!").unwrap_err();
+    assert!(error.contains("invalid UTF-8 at line 20002"), "{}", error);
+    let buffer = editor.buffer().unwrap();
+    assert!(buffer.is_modified() && buffer.is_readonly());
+    assert_eq!(buffer.line(0), Some("hihello"));
+    assert_eq!(buffer.line_count(), 20001);
+    assert_eq!(fs::read(&file.0).unwrap(), bytes);
+    editor.execute(&format!("w {}", copy.0.display())).unwrap();
+    assert!(fs::read(&copy.0).unwrap().starts_with(b"hihello\nxxx"));
+}
+
+#[test]
+fn views_not_written() {
+    let file = Temp(env::temp_dir().join(format!("rusty-vim-{}-view", process::id())));
+    fs::write(&file.0, b"caf\xe9\n").unwrap();
+    let backend = HeadlessBackend::new(40, 6);
+    let mut editor = Editor::with_backend(Vec::new(), Box::new(backend)).unwrap();
+    editor.execute("set noswapfile").unwrap();
+    editor.open(file.0.to_str().unwrap()).unwrap();
+    editor.feed_keys(b"h").unwrap();
+    let line = editor.buffer().unwrap().line(0).unwrap();
+    assert!(line.starts_with("00000000: 6361 66e9 0a"), "{}", line);
+    assert!(editor.execute("w!").is_err());
+    editor.feed_keys(b"x").unwrap();
+    assert!(editor.execute("w!").is_err());
+    assert_eq!(fs::read(&file.0).unwrap(), b"caf\xe9\n");
+}
+
+#[test]
+fn file_renames() {
+    let (mut editor, backend, _file) = editor("rename", 60, 6);
+    let other = scratch("renamed");
+    editor
+        .execute(&format!("file {}", other.0.display()))
+        .unwrap();
+    // the file now named is not taken as one changed since it was read
+    editor.execute("checktime").unwrap();
+    assert!(!backend.lines().concat().contains("changed on disk"));
+    editor.feed_keys(b"x").unwrap();
+    editor.execute("w").unwrap();
+    assert_eq!(fs::read_to_string(&other.0).unwrap(), "ello\nworld\n");
+}
+
+#[test]
+fn source_recursion() {
+    let (mut editor, _backend, _file) = editor("source", 40, 6);
+    let script = Temp(env::temp_dir().join(format!("rusty-vim-{}-loop.rv", process::id())));
+    fs::write(
+        &script.0,
+        format!("set ts+=1\nsource {}\n", script.0.display()),
+    )
+    .unwrap();
+    let error = editor
+        .execute(&format!("so {}", script.0.display()))
+        .unwrap_err();
+    assert!(
+        error.ends_with("line 2: Command too recursive"),
+        "{}",
+        error
+    );
+    editor.execute("set ts?").unwrap();
+    assert_eq!(editor.message(), "tabstop=23");
+}
+
+#[test]
+fn substitute_bar() {
+    let (mut editor, _backend, _file) = editor("subst", 40, 6);
+    editor.execute("s/hello/a|b/ | s/a|b/c|d/").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(0), Some("c|d"));
+    assert_eq!(
+        editor.execute("%s/missing/x/"),
+        Err(String::from("Pattern not found: missing"))
+    );
+    editor.execute("%s/missing/x/e | %s/world/there/g").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(1), Some("there"));
+    assert_eq!(
+        editor.execute("s/a/b/x"),
+        Err(String::from("Invalid flags: x"))
+    );
+}
+
+#[test]
+fn file_deleted() {
+    let (mut editor, _backend, file) = editor("deleted", 60, 6);
+    fs::remove_file(&file.0).unwrap();
+    editor.execute("checktime").unwrap();
+    let expected = format!("File \"{}\" no longer available", file.0.display());
+    assert_eq!(editor.message(), expected);
+    // reported once, and writing puts the file back
+    editor.execute("checktime").unwrap();
+    assert_eq!(editor.message(), "");
+    editor.execute("w").unwrap();
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "hello\nworld\n");
+}
+
+#[test]
+fn file_changed_on_disk() {
+    let (mut editor, backend, file) = editor("changed", 140, 6);
+    // the same contents with a new modification time is not a change
+    rewrite(&file.0, "hello\nworld\n", 10);
+    editor.execute("checktime").unwrap();
+    assert!(!backend.lines().concat().contains("changed on disk"));
+    rewrite(&file.0, "hello\nthere\n", 20);
+    editor.execute("checktime").unwrap();
+    assert!(
+        backend
+            .lines()
+            .concat()
+            .contains("has changed on disk since it was read")
+    );
+    editor.feed_keys(b"l").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(1), Some("there"));
+    // with autoread a buffer without changes is reloaded without asking
+    editor.execute("set autoread").unwrap();
+    rewrite(&file.0, "hello\nagain\n", 30);
+    editor.execute("checktime").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(1), Some("again"));
+    assert!(editor.message().ends_with("reloaded from disk"));
+    // a buffer with changes is still asked about, and keeping them keeps `:wq` from writing
+    editor.feed_keys(b"x").unwrap();
+    rewrite(&file.0, "hello\nlast\n", 40);
+    editor.execute("checktime").unwrap();
+    assert!(
+        backend
+            .lines()
+            .concat()
+            .contains("and the buffer has changes too")
+    );
+    editor.feed_keys(b"k").unwrap();
+    assert!(editor.execute("wq").is_err());
+    assert!(editor.is_running());
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "hello\nlast\n");
+}
+
+#[test]
+fn readonly_and_nomodifiable() {
+    let (mut editor, backend, file) = editor("readonly", 60, 6);
+    editor.execute("setlocal readonly").unwrap();
+    assert!(backend.lines()[4].contains("[RO]"));
+    editor.feed_keys(b"x").unwrap();
+    let refused = Err(String::from("'readonly' option is set (add ! to override)"));
+    assert_eq!(editor.execute("w"), refused);
+    assert_eq!(editor.execute("wq"), refused);
+    assert!(editor.execute("wa").is_err());
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "hello\nworld\n");
+    editor.execute("w!").unwrap();
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "ello\nworld\n");
+    // nomodifiable refuses the edits themselves
+    editor.execute("setlocal nomodifiable").unwrap();
+    editor.feed_keys(b"x").unwrap();
+    assert_eq!(editor.message(), "Cannot make changes, 'modifiable' is off");
+    editor.feed_keys(b"ihi\x1b").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(0), Some("ello"));
+    let refused = Err(String::from("Cannot make changes, 'modifiable' is off"));
+    assert_eq!(editor.execute("s/ello/x/"), refused);
+    assert_eq!(editor.execute("r"), refused);
+    assert!(!editor.buffer().unwrap().is_modified());
+}
+
+#[test]
+fn edit_read_and_saveas() {
+    let (mut editor, _backend, file) = editor("edit", 60, 6);
+    let other = scratch("edit-other");
+    let copy = Temp(env::temp_dir().join(format!("rusty-vim-{}-edit-copy", process::id())));
+    editor.feed_keys(b"x").unwrap();
+    assert_eq!(
+        editor.execute("e"),
+        Err(String::from(
+            "No write since last change (add ! to override)"
+        ))
+    );
+    // `:e!` drops the change
+    editor.execute("e!").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(0), Some("hello"));
+    assert!(!editor.buffer().unwrap().is_modified());
+    // `:r` puts the file below the cursor
+    editor.execute(&format!("r {}", other.0.display())).unwrap();
+    let buffer = editor.buffer().unwrap();
+    assert_eq!(buffer.line_count(), 4);
+    assert_eq!(
+        (buffer.line(1), buffer.line(2)),
+        (Some("hello"), Some("world"))
+    );
+    assert!(buffer.is_modified());
+    assert!(editor.execute("r /nonexistent/file").is_err());
+    // `:saveas` writes the buffer to a new file, which the buffer then edits
+    assert_eq!(
+        editor.execute(&format!("saveas {}", other.0.display())),
+        Err(String::from("File exists (add ! to override)"))
+    );
+    editor
+        .execute(&format!("saveas {}", copy.0.display()))
+        .unwrap();
+    let buffer = editor.buffer().unwrap();
+    assert_eq!(buffer.filename(), copy.0.to_str());
+    assert!(!buffer.is_modified());
+    assert_eq!(
+        fs::read_to_string(&copy.0).unwrap(),
+        "hello\nhello\nworld\nworld\n"
+    );
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "hello\nworld\n");
+    editor.feed_keys(b"x").unwrap();
+    editor.execute("w").unwrap();
+    let buffer = editor.buffer().unwrap();
+    let lines: Vec<&str> = (0..4).filter_map(|y| buffer.line(y)).collect();
+    assert_eq!(
+        fs::read_to_string(&copy.0).unwrap(),
+        lines.join("\n") + "\n"
+    );
+    assert_ne!(lines, ["hello", "hello", "world", "world"]);
+}
+
+#[test]
+fn quit_all_with_changes() {
+    let (mut editor, backend, file) = editor("qa", 60, 8);
+    let second = scratch("qa-second");
+    editor
+        .execute(&format!("e {}", second.0.display()))
+        .unwrap();
+    editor.feed_keys(b"x").unwrap();
+    editor.execute("b2").unwrap();
+    editor.feed_keys(b"x").unwrap();
+    editor.execute("w").unwrap();
+    editor.feed_keys(b"x").unwrap();
+    editor.execute("b3").unwrap();
+    // the first buffer with changes is the one shown, every one of them is listed
+    assert_eq!(
+        editor.execute("qa"),
+        Err(String::from(
+            "No write since last change for buffer 2 (add ! to override)"
+        ))
+    );
+    assert!(editor.is_running());
+    assert_eq!(editor.buffer().unwrap().filename(), file.0.to_str());
+    assert!(
+        backend
+            .lines()
+            .contains(&String::from("No write since last change for:"))
+    );
+    editor.execute("w").unwrap();
+    assert_eq!(
+        editor.execute("qa"),
+        Err(String::from(
+            "No write since last change for buffer 3 (add ! to override)"
+        ))
+    );
+    assert_eq!(editor.buffer().unwrap().filename(), second.0.to_str());
+    editor.execute("wqa").unwrap();
+    assert!(!editor.is_running());
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "llo\nworld\n");
+    assert_eq!(fs::read_to_string(&second.0).unwrap(), "ello\nworld\n");
+}
+
+#[test]
+fn quit_all_dropping_changes() {
+    let (mut editor, _backend, file) = editor("qa-force", 60, 6);
+    editor.feed_keys(b"x").unwrap();
+    editor.execute("qa!").unwrap();
+    assert!(!editor.is_running());
+    assert_eq!(fs::read_to_string(&file.0).unwrap(), "hello\nworld\n");
+}
+
+#[test]
+fn argdo_and_bufdo() {
+    let (mut editor, _backend, _file) = editor("do", 60, 6);
+    let files: Vec<Temp> = ["do-a", "do-b", "do-c"].into_iter().map(scratch).collect();
+    fs::write(&files[0].0, "foo\n").unwrap();
+    fs::write(&files[2].0, "foo foo\nfoo\n").unwrap();
+    rewrite(&files[1].0, "hello\n", 0);
+    let untouched = fs::metadata(&files[1].0).unwrap().modified().unwrap();
+    let names: Vec<String> = files
+        .iter()
+        .map(|file| file.0.display().to_string())
+        .collect();
+    editor
+        .execute(&format!("args {}", names.join(" ")))
+        .unwrap();
+    editor.execute("argdo %s/foo/bar/ge | update").unwrap();
+    assert_eq!(fs::read_to_string(&files[0].0).unwrap(), "bar\n");
+    assert_eq!(fs::read_to_string(&files[2].0).unwrap(), "bar bar\nbar\n");
+    // a file without a match is neither changed nor written
+    let modified = fs::metadata(&files[1].0).unwrap().modified().unwrap();
+    assert_eq!(modified, untouched);
+    // without `e` the first buffer without a match stops bufdo
+    assert_eq!(
+        editor.execute("bufdo %s/bar/baz/g | update"),
+        Err(String::from("Pattern not found: bar"))
+    );
+    assert_eq!(fs::read_to_string(&files[0].0).unwrap(), "bar\n");
+    editor.execute("bufdo %s/bar/baz/ge | update").unwrap();
+    assert_eq!(fs::read_to_string(&files[0].0).unwrap(), "baz\n");
+    assert_eq!(fs::read_to_string(&files[2].0).unwrap(), "baz baz\nbaz\n");
+    assert_eq!(fs::read_to_string(&files[1].0).unwrap(), "hello\n");
+    assert!(editor.execute("argdo").is_err());
+}
+
+#[test]
+fn softtabstop_after_tab() {
+    let (mut editor, _backend, _file) = editor("sts", 40, 6);
+    editor.execute("setlocal expandtab softtabstop=4").unwrap();
+    editor.execute("s/hello/\tx/").unwrap();
+    // the tab takes the text to column 9, the next stop is at 12
+    editor.feed_keys(b"A\t|\x1b").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(0), Some("\tx   |"));
+}
+
+#[test]
+fn abbreviation_before_multibyte() {
+    let (mut editor, _backend, _file) = editor("abbrev", 40, 6);
+    editor.execute("iab x ex").unwrap();
+    editor.feed_keys(b"Ox.\x1b").unwrap();
+    assert_eq!(editor.buffer().unwrap().line(0), Some("ex."));
+    // the bytes of a letter such as U+05E7 do not end the word
+    editor.feed_keys(b"Ox\xd7\xa7\x1b").unwrap();
+    let line = editor.buffer().unwrap().line(0).unwrap();
+    assert!(line.starts_with('x'), "{}", line);
+}